@@ -1,4 +1,4 @@
-use std::clone;
+use std::fmt;
 
 use thiserror::Error;
 use crate::lexer::*;
@@ -8,34 +8,148 @@ pub enum ExprError {
     #[error("Was expecting {expected:?},  {found:?}")]
     FailedConversion{expected: String, found: String},
 
-    #[error("Only Int or Float is Allowed to be first element")]
+    #[error("`{raw}` doesn't fit in {ty}, which holds {range}")]
+    Overflow{raw: String, ty: String, range: String},
+
+    #[error("Only numbers, strings, characters and booleans are literals")]
     InvalidFirstElement
 }
 
+/*
+    raw is an optionally negative number with an optional 0x / 0b / 0o prefix, checked
+    against the range of ty. u64 values above i64::MAX keep their bits, their literal is a
+    Literal::Unsigned so they aren't read back as negative.
+*/
+fn parse_integer(raw: &str, ty: &str) -> Result<i64, ExprError> {
+    let (min, max): (i128, i128) = match ty {
+        "i8" => (i8::MIN.into(), i8::MAX.into()),
+        "i16" => (i16::MIN.into(), i16::MAX.into()),
+        "i32" => (i32::MIN.into(), i32::MAX.into()),
+        "u8" => (0, u8::MAX.into()),
+        "u16" => (0, u16::MAX.into()),
+        "u32" => (0, u32::MAX.into()),
+        "u64" => (0, u64::MAX.into()),
+        _ => (i64::MIN.into(), i64::MAX.into()),
+    };
+    let overflow = || ExprError::Overflow {
+        raw: raw.to_string(),
+        ty: ty.to_string(),
+        range: format!("{}..={}", min, max),
+    };
+
+    let (negative, digits) = match raw.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, raw),
+    };
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x") => (16, &digits[2..]),
+        Some("0b") => (2, &digits[2..]),
+        Some("0o") => (8, &digits[2..]),
+        _ => (10, digits),
+    };
+
+    /* the lexer only lets valid digits through, so this can only fail on overflow */
+    let magnitude = i128::from_str_radix(digits, radix).map_err(|_| overflow())?;
+    let value = if negative { -magnitude } else { magnitude };
+
+    if value < min || value > max {
+        return Err(overflow());
+    }
+
+    Ok(value as i64)
+}
+
 #[derive(Debug)]
 pub struct Program {
-    pub exprs: Vec<Expr>
+    pub stmts: Vec<Stmt>
 }
 
 
 impl Program{
     pub fn new() -> Program {
         Program{
-            exprs: vec![]
+            stmts: vec![]
+        }
+    }
+}
+
+/* statements in `{ }`, span covers the braces */
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub span: Span,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    /* x := 10 */
+    Let { name: String, value: Expr, span: Span },
+
+    /* x = 10, x += 1 is stored as x = x + 1 */
+    Assign { name: String, value: Expr, span: Span },
+
+    /* an expression whose value is thrown away, like print(x) */
+    Expr(Expr),
+
+    If { condition: Expr, then_block: Block, else_block: Option<Block>, span: Span },
+
+    /* `outer: while condition { ... }` */
+    While { label: Option<String>, condition: Expr, body: Block, span: Span },
+
+    /* `for i in start..end step n { ... }`, with `..=` end is part of the range. step defaults to 1 */
+    For {
+        label: Option<String>,
+        variable: String,
+        start: Expr,
+        end: Expr,
+        inclusive: bool,
+        step: Option<Expr>,
+        body: Block,
+        span: Span,
+    },
+
+    /* without a label the innermost loop is meant, only `loop` can break with a value */
+    Break { label: Option<String>, value: Option<Expr>, span: Span },
+
+    Continue { label: Option<String>, span: Span },
+
+    Return { value: Option<Expr>, span: Span },
+
+    Proc { name: String, params: Vec<String>, body: Block, span: Span },
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Expr(expr) => expr.span(),
+            Stmt::Let { span, .. }
+            | Stmt::Assign { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::For { span, .. }
+            | Stmt::Break { span, .. }
+            | Stmt::Continue { span, .. }
+            | Stmt::Return { span, .. }
+            | Stmt::Proc { span, .. } => *span,
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum Literal {
-    Integer(i32),
-    FloatingPoint(f32),
+    Integer(i64),
+    Unsigned(u64),
+    FloatingPoint(f64),
     String(String),
+    Char(char),
     Boolean(bool),
     Symbol(String),
 }
 
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub enum Operator {
     UnaryPass,
@@ -58,65 +172,127 @@ pub enum Operator {
     NotEqual,
 
     BooleanAnd,
+    BooleanOr,
     BooleanEqual,
 
+    /* the string pieces of an interpolated string, joined in order */
+    Concat,
+
+    /* the text of a value, as print would show it */
+    Format,
+
     Call(String),
-    Define(Expr),
     Index,
+}
 
 
-    Assignment,
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operator::UnaryPass => write!(f, "unary `+`"),
+            Operator::UnaryMinus => write!(f, "unary `-`"),
+            Operator::LogicalNegate => write!(f, "`!`"),
+            Operator::Multiply => write!(f, "`*`"),
+            Operator::Substract | Operator::Minus => write!(f, "`-`"),
+            Operator::Modulos => write!(f, "`%`"),
+            Operator::Division => write!(f, "`/`"),
+            Operator::Plus => write!(f, "`+`"),
+            Operator::LessThan => write!(f, "`<`"),
+            Operator::GreaterThan => write!(f, "`>`"),
+            Operator::LessThanOrEqual => write!(f, "`<=`"),
+            Operator::GreaterThantOrEqual => write!(f, "`>=`"),
+            Operator::Equal | Operator::BooleanEqual => write!(f, "`==`"),
+            Operator::NotEqual => write!(f, "`!=`"),
+            Operator::BooleanAnd => write!(f, "`&&`"),
+            Operator::BooleanOr => write!(f, "`||`"),
+            Operator::Concat => write!(f, "string interpolation"),
+            Operator::Format => write!(f, "formatting"),
+            Operator::Call(name) => write!(f, "call to `{}`", name),
+            Operator::Index => write!(f, "indexing"),
+        }
+    }
 }
 
-
 #[derive(Debug, Clone)]
 pub struct OpExpr {
     pub op: Operator,
     pub args: Vec<Expr>,
+    pub span: Span,
 }
 
 impl OpExpr {
     pub fn operator(op: Operator, left: Expr, right: Expr) -> OpExpr {
-        OpExpr { op, args: vec![left, right]}
+        let span = left.span().to(right.span());
+        OpExpr { op, args: vec![left, right], span }
     }
     
-    pub fn function_op(op: Operator, args: Expr) -> OpExpr {
-        OpExpr { op, args: vec![args] }
+    pub fn unary(op: Operator, operand: Expr, span: Span) -> OpExpr {
+        let span = span.to(operand.span());
+        OpExpr { op, args: vec![operand], span }
+    }
+
+    pub fn call(name: &str, args: Vec<Expr>, span: Span) -> OpExpr {
+        OpExpr { op: Operator::Call(name.to_string()), args, span }
+    }
+
+    pub fn concat(parts: Vec<Expr>, span: Span) -> OpExpr {
+        OpExpr { op: Operator::Concat, args: parts, span }
+    }
+
+    pub fn function_op(op: Operator, args: Expr, span: Span) -> OpExpr {
+        OpExpr { op, args: vec![args], span }
     }
 }
 
 
+/* `outer: loop { ... }`, its value is whatever `break` hands it */
+#[derive(Debug, Clone)]
+pub struct LoopExpr {
+    pub label: Option<String>,
+    pub body: Block,
+    pub span: Span,
+}
+
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone)]
 pub enum Expr {
     OpExpr(Box<OpExpr>),
-    OpLiteral(Box<Literal>),
-    OPComment,
+    OpLiteral(Box<Literal>, Span),
+    Loop(Box<LoopExpr>),
 }
 
 impl Expr {
-    pub fn new_literal(token: TokenType)-> Result<Expr, ExprError>{
-        match token {
-            TokenType::Numeric { raw, hint }=>{
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::OpExpr(op_expr) => op_expr.span,
+            Expr::OpLiteral(_, span) => *span,
+            Expr::Loop(loop_expr) => loop_expr.span,
+        }
+    }
+
+    pub fn new_literal(token: Token)-> Result<Expr, ExprError>{
+        let span = token.span;
+        match token.token_type {
+            TokenType::Numeric { raw, hint, suffix }=>{
                 match hint {
                     NumericType::Integer => {
-                        let number:Result<i32, _> = raw.parse();
-                        match number {
-                            Ok(number)=>{
-                                Ok(Expr::OpLiteral(Box::new(Literal::Integer(number))))
-                           },
-                           Err(_)=>{
-                            Err(ExprError::FailedConversion{
-                                expected: "int".to_string(),
-                                found: raw
-                            })
-                           }
-                        }
+                        let ty = suffix.as_deref().unwrap_or("i64");
+                        let number = parse_integer(&raw, ty)?;
+                        let literal = if ty == "u64" { Literal::Unsigned(number as u64) } else { Literal::Integer(number) };
+                        Ok(Expr::OpLiteral(Box::new(literal), span))
                     },
                     NumericType::FloatingPoint=>{
-                        let number:Result<f32, _> = raw.parse();
+                        let ty = suffix.as_deref().unwrap_or("f64");
+                        let number:Result<f64, _> = raw.parse();
                         match number {
+                           Ok(number) if ty == "f32" && !(number as f32).is_finite() => {
+                               Err(ExprError::Overflow { raw, ty: ty.to_string(), range: format!("±{:e}", f32::MAX) })
+                           }
+                           Ok(number) if !number.is_finite() => {
+                               Err(ExprError::Overflow { raw, ty: ty.to_string(), range: format!("±{:e}", f64::MAX) })
+                           }
                            Ok(number)=>{
-                               Ok(Expr::OpLiteral(Box::new(Literal::FloatingPoint(number))))
+                               Ok(Expr::OpLiteral(Box::new(Literal::FloatingPoint(number)), span))
                            },
                            Err(_)=>{
                             Err(ExprError::FailedConversion{
@@ -128,6 +304,11 @@ impl Expr {
                     }
                 }
             },
+            TokenType::String(s) => Ok(Expr::OpLiteral(Box::new(Literal::String(s)), span)),
+            TokenType::Char(c) => Ok(Expr::OpLiteral(Box::new(Literal::Char(c)), span)),
+            TokenType::Symobl(sym) if sym == "true" || sym == "false" => {
+                Ok(Expr::OpLiteral(Box::new(Literal::Boolean(sym == "true")), span))
+            }
             _=>{
                 Err(ExprError::InvalidFirstElement)
             }
@@ -1,104 +1,536 @@
 use crate::ast::*;
+use crate::lexer::Span;
 
+use std::collections::HashMap;
 use std::fs;
-use std::fs::File;
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::fmt::{self, Write};
+use std::io;
 use std::process::Command;
 
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CompileError {
+    #[error("{what} is not supported by the code generator yet")]
+    Unsupported { what: String, span: Span },
+
+    #[error("There is no procedure called `{name}`")]
+    UnknownProc { name: String, span: Span },
+
+    #[error("Wrong number of arguments for `{name}`, expected {expected} but found {found}")]
+    ArgumentCount { name: String, expected: usize, found: usize, span: Span },
+
+    #[error("`{name}` is defined more than once")]
+    DuplicateProc { name: String, span: Span },
+
+    #[error("`{name}` isn't defined")]
+    UndefinedVariable { name: String, span: Span },
+
+    #[error("Was expecting {expected}, found {found}")]
+    TypeMismatch { expected: Type, found: Type, span: Span },
+
+    #[error("A `for` loop can't step by 0")]
+    ZeroStep { span: Span },
+
+    #[error("Couldn't write {path:?}, {source}")]
+    Io { path: String, source: io::Error },
+
+    #[error("{tool} failed, {message}")]
+    Toolchain { tool: String, message: String },
+}
+
+/*
+    what the 8 bytes of a value on the stack hold, floats are kept as their bits and strings
+    are the address of their length, which the bytes follow
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    Int,
+    UInt,
+    Float,
+    Char,
+    Str,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "an integer"),
+            Type::UInt => write!(f, "an unsigned integer"),
+            Type::Float => write!(f, "a float"),
+            Type::Char => write!(f, "a character"),
+            Type::Str => write!(f, "a string"),
+        }
+    }
+}
+
+/* where break and continue of a loop jump to */
+struct LoopLabels {
+    label: Option<String>,
+    continue_label: String,
+    break_label: String,
+
+    /* values on the stack when the loop was entered, anything above is dropped on the way out */
+    stack_depth: usize,
+}
+
+/* where the value of a variable lives */
+#[derive(Debug, Clone)]
+enum Storage {
+    /* a `resq` in .bss, for variables of the main program */
+    Global(String),
+
+    /* [rbp-8*n] in the frame of a procedure */
+    Slot(usize),
+
+    /* the counter of a for loop */
+    Register(&'static str),
+}
+
+#[derive(Debug, Clone)]
+struct Variable {
+    storage: Storage,
+    ty: Type,
+}
+
+impl Storage {
+    fn operand(&self) -> String {
+        match self {
+            Storage::Global(label) => format!("QWORD [{}]", label),
+            Storage::Slot(n) => format!("QWORD [rbp-{}]", 8 * n),
+            Storage::Register(register) => register.to_string(),
+        }
+    }
+}
+
+/* the procedure being compiled */
+struct Frame {
+    /* stack slots below rbp in use, the parameters take the first ones */
+    slot_count: usize,
+
+    return_label: String,
+
+    /* how many of COUNTER_REGISTERS the body uses, the prologue saves them */
+    counters_used: usize,
+}
+
 pub struct Compile {
-    output_file: File,
+    output_path: String,
+    asm: String,
+
+    /* the source file, runtime errors point into it */
+    file_name: String,
+
+    /* read only data, like the messages of runtime errors */
+    rodata: String,
+
+    /* compiled procedures, they go after the exit of the main program */
+    functions: String,
+
+    /* parameter count and definition of every top level procedure */
+    procs: HashMap<String, (usize, Span)>,
+
+    frame: Option<Frame>,
+
+    /* blocks the statement being compiled is in, 0 at the top level */
+    block_depth: usize,
+
+    /* numbers the generated labels, so every jump target is unique */
+    label_count: usize,
+
+    /* values pushed by expressions that are still being evaluated */
+    stack_depth: usize,
+
+    /* innermost last */
+    loops: Vec<LoopLabels>,
+
+    /* for loops the current statement is in that count in one of COUNTER_REGISTERS */
+    counters: usize,
+
+    /* variables visible from the current statement, one map per block and innermost last */
+    scopes: Vec<HashMap<String, Variable>>,
+
+    /* `resq` lines for the global variables */
+    bss: String,
+
+    /* the .rodata label of every string literal, so each text is stored once */
+    strings: HashMap<String, String>,
 }
 
-impl Compile {
-    pub fn new(output_path: &str) -> Compile {
-        if !fs::metadata(output_path).is_ok() {
-            match fs::create_dir(output_path) {
-                Ok(_) => {}
-                Err(e) => panic!("Error Creating file target {}", e),
-            }
+/* callee saved, so the counters survive calls */
+const COUNTER_REGISTERS: [&str; 4] = ["r12", "r13", "r14", "r15"];
+
+/* System V, the first six arguments go in registers and the rest on the stack */
+const ARGUMENT_REGISTERS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+
+/* printable text is kept readable in a string, everything else is written as numbers */
+fn nasm_bytes(bytes: &[u8]) -> String {
+    let mut parts = vec![];
+    let mut text = String::new();
+
+    for &byte in bytes {
+        if (b' '..=b'~').contains(&byte) && byte != b'"' {
+            text.push(byte as char);
         } else {
-            if fs::metadata(format!("{}/{}", output_path, "output.asm")).is_ok() {
-                match fs::remove_file(format!("{}/{}", output_path, "output.asm")) {
-                    Err(e) => {
-                        panic!("Error While Deleting Prev File {}", e)
-                    }
-                    Ok(_) => {}
-                }
+            if !text.is_empty() {
+                parts.push(format!("\"{}\"", std::mem::take(&mut text)));
             }
+            parts.push(byte.to_string());
         }
+    }
+    if !text.is_empty() {
+        parts.push(format!("\"{}\"", text));
+    }
+    if parts.is_empty() {
+        parts.push("0".to_string());
+    }
 
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(format!("{}/{}", output_path, "output.asm"));
-        match file {
-            Ok(file) => {
-                let mut comp = Compile { output_file: file };
+    parts.join(", ")
+}
 
-                comp.init_global_func();
-                comp
-            }
-            Err(e) => {
-                panic!("Error caused while creating/ opening file {}", e)
+fn proc_label(name: &str) -> String {
+    mangle("proc", name)
+}
+
+/* nasm labels are ascii, so anything else (and `_`, to keep it unambiguous) is spelled out */
+fn mangle(prefix: &str, name: &str) -> String {
+    let mut label = format!("{}_", prefix);
+    for c in name.chars() {
+        match c {
+            '_' => label.push_str("__"),
+            c if c.is_ascii_alphanumeric() => label.push(c),
+            c => {
+                let _ = write!(label, "_u{:x}_", c as u32);
             }
         }
     }
+    label
+}
 
-    pub fn write(&mut self, line_to_write: &str) {
-        let line = writeln!(self.output_file, "{}", line_to_write);
+impl Compile {
+    pub fn new(output_path: &str, file_name: &str) -> Compile {
+        let mut comp = Compile {
+            output_path: output_path.to_string(),
+            asm: String::new(),
+            file_name: file_name.to_string(),
+            rodata: String::new(),
+            functions: String::new(),
+            procs: HashMap::new(),
+            frame: None,
+            block_depth: 0,
+            label_count: 0,
+            stack_depth: 0,
+            loops: vec![],
+            counters: 0,
+            scopes: vec![HashMap::new()],
+            bss: String::new(),
+            strings: HashMap::new(),
+        };
 
-        match line {
-            Err(e) => {
-                panic!("Error caused while writing to file with {}", e)
-            }
-            Ok(_) => {}
-        }
+        comp.init_global_func();
+        comp
+    }
+
+    pub fn write(&mut self, line_to_write: &str) {
+        // writing into a String can't fail
+        let _ = writeln!(self.asm, "{}", line_to_write);
     }
 
     fn init_global_func(&mut self) {
         self.write("BITS 64");
         self.write("segment .text");
-        self.write("dump:");
-        self.write("    mov     r9, -3689348814741910323");
+
+        /* print_i64 / print_u64 write rdi to stdout, as signed / unsigned */
+        self.write("print_u64:");
+        self.write("    xor     esi, esi");
+        self.write("    jmp     print_integer");
+        self.write("print_i64:");
+        self.write("    xor     esi, esi");
+        self.write("    test    rdi, rdi");
+        self.write("    jns     print_integer");
+        /* i64::MIN negates to itself, which is the right magnitude when read unsigned */
+        self.write("    neg     rdi");
+        self.write("    mov     esi, 1");
+
+        /* rdi is the unsigned magnitude, esi is 1 when a `-` goes in front */
+        self.write("print_integer:");
         self.write("    sub     rsp, 40");
-        self.write("    mov     BYTE [rsp+31], 10");
-        self.write("    lea     rcx, [rsp+30]");
-        self.write(".L2:");
-        self.write("    mov     rax, rdi");
-        self.write("    lea     r8, [rsp+32]");
-        self.write("    mul     r9");
+        self.write("    lea     rcx, [rsp+32]");
         self.write("    mov     rax, rdi");
-        self.write("    sub     r8, rcx");
-        self.write("    shr     rdx, 3");
-        self.write("    lea     rsi, [rdx+rdx*4]");
-        self.write("    add     rsi, rsi");
-        self.write("    sub     rax, rsi");
-        self.write("    add     eax, 48");
-        self.write("    mov     BYTE [rcx], al");
-        self.write("    mov     rax, rdi");
-        self.write("    mov     rdi, rdx");
-        self.write("    mov     rdx, rcx");
-        self.write("    sub     rcx, 1");
-        self.write("    cmp     rax, 9");
-        self.write("    ja      .L2");
-        self.write("    lea     rax, [rsp+32]");
-        self.write("    mov     edi, 1");
-        self.write("    sub     rdx, rax");
-        self.write("    xor     eax, eax");
-        self.write("    lea     rsi, [rsp+32+rdx]");
-        self.write("    mov     rdx, r8");
+        self.write("    mov     r8, 10");
+        self.write(".digit:");
+        self.write("    xor     edx, edx");
+        self.write("    div     r8");
+        self.write("    add     dl, 48");
+        self.write("    dec     rcx");
+        self.write("    mov     [rcx], dl");
+        self.write("    test    rax, rax");
+        self.write("    jnz     .digit");
+        self.write("    test    esi, esi");
+        self.write("    jz      .write");
+        self.write("    dec     rcx");
+        self.write("    mov     BYTE [rcx], 45");
+        self.write(".write:");
         self.write("    mov     rax, 1");
+        self.write("    mov     rdi, 1");
+        self.write("    mov     rsi, rcx");
+        self.write("    lea     rdx, [rsp+32]");
+        self.write("    sub     rdx, rcx");
         self.write("    syscall");
         self.write("    add     rsp, 40");
         self.write("    ret");
+
+        /*
+            print_f64 writes xmm0 to stdout, with up to 6 decimals and trailing
+            zeros dropped. Below 1e-4 and from 1e12 on it's written as d.ddddddeN instead.
+        */
+        self.write("print_f64:");
+        self.write("    push    rbp");
+        self.write("    mov     rbp, rsp");
+        /* the text is built forwards from rbp-96 at rdi, append_u64 uses rbp-32 .. rbp-1 */
+        self.write("    sub     rsp, 96");
+        self.write("    lea     rdi, [rbp-96]");
+        /* r9d is 1 when it's written with an exponent, r10 is the exponent */
+        self.write("    xor     r9d, r9d");
+        self.write("    xor     r10d, r10d");
+        self.write("    movq    rax, xmm0");
+        self.write("    btr     rax, 63");
+        self.write("    jnc     .positive");
+        self.write("    mov     BYTE [rdi], 45");
+        self.write("    inc     rdi");
+        self.write(".positive:");
+        self.write("    movq    xmm0, rax");
+        self.write("    mov     rdx, rax");
+        self.write("    shr     rdx, 52");
+        self.write("    cmp     rdx, 0x7ff");
+        self.write("    jne     .finite");
+        self.write("    shl     rax, 12");
+        self.write("    jz      .infinity");
+        /* "NaN", without a sign */
+        self.write("    mov     DWORD [rbp-96], 0x4e614e");
+        self.write("    lea     rdi, [rbp-93]");
+        self.write("    jmp     .write");
+        self.write(".infinity:");
+        /* "inf" */
+        self.write("    mov     DWORD [rdi], 0x666e69");
+        self.write("    add     rdi, 3");
+        self.write("    jmp     .write");
+        self.write(".finite:");
+        self.write("    test    rax, rax");
+        self.write("    jz      .fixed");
+        self.write("    ucomisd xmm0, [rel f64_exponent_above]");
+        self.write("    jae     .scale_down");
+        self.write("    ucomisd xmm0, [rel f64_exponent_below]");
+        self.write("    jae     .fixed");
+        self.write("    mov     r9d, 1");
+        self.write(".scale_up:");
+        self.write("    mulsd   xmm0, [rel f64_ten]");
+        self.write("    dec     r10");
+        self.write("    ucomisd xmm0, [rel f64_one]");
+        self.write("    jb      .scale_up");
+        self.write("    jmp     .fixed");
+        self.write(".scale_down:");
+        self.write("    mov     r9d, 1");
+        self.write(".scale_down_again:");
+        self.write("    divsd   xmm0, [rel f64_ten]");
+        self.write("    inc     r10");
+        self.write("    ucomisd xmm0, [rel f64_ten]");
+        self.write("    jae     .scale_down_again");
+        /* millionths, rounded to nearest */
+        self.write(".fixed:");
+        self.write("    mulsd   xmm0, [rel f64_million]");
+        self.write("    cvtsd2si rax, xmm0");
+        /* 9.9999999 rounds up to 10.000000, which is 1.000000 with the next exponent */
+        self.write("    test    r9d, r9d");
+        self.write("    jz      .split");
+        self.write("    cmp     rax, 10000000");
+        self.write("    jb      .split");
+        self.write("    mov     rax, 1000000");
+        self.write("    inc     r10");
+        self.write(".split:");
+        self.write("    xor     edx, edx");
+        self.write("    mov     r8, 1000000");
+        self.write("    div     r8");
+        self.write("    push    rdx");
+        self.write("    call    .append_u64");
+        self.write("    pop     rax");
+        self.write("    mov     BYTE [rdi], 46");
+        self.write("    inc     rdi");
+        self.write("    mov     ecx, 6");
+        self.write("    mov     r8, 10");
+        self.write(".decimal:");
+        self.write("    xor     edx, edx");
+        self.write("    div     r8");
+        self.write("    add     dl, 48");
+        self.write("    mov     [rdi+rcx-1], dl");
+        self.write("    dec     ecx");
+        self.write("    jnz     .decimal");
+        self.write("    add     rdi, 6");
+        /* at least one decimal stays, so 2.0 doesn't read like an integer */
+        self.write(".trim:");
+        self.write("    cmp     BYTE [rdi-1], 48");
+        self.write("    jne     .exponent");
+        self.write("    cmp     BYTE [rdi-2], 46");
+        self.write("    je      .exponent");
+        self.write("    dec     rdi");
+        self.write("    jmp     .trim");
+        self.write(".exponent:");
+        self.write("    test    r9d, r9d");
+        self.write("    jz      .write");
+        self.write("    mov     BYTE [rdi], 101");
+        self.write("    inc     rdi");
+        self.write("    mov     rax, r10");
+        self.write("    test    rax, rax");
+        self.write("    jns     .exponent_digits");
+        self.write("    mov     BYTE [rdi], 45");
+        self.write("    inc     rdi");
+        self.write("    neg     rax");
+        self.write(".exponent_digits:");
+        self.write("    call    .append_u64");
+        self.write(".write:");
+        self.write("    mov     rax, 1");
+        self.write("    lea     rsi, [rbp-96]");
+        self.write("    mov     rdx, rdi");
+        self.write("    sub     rdx, rsi");
+        self.write("    mov     rdi, 1");
+        self.write("    syscall");
+        self.write("    mov     rsp, rbp");
+        self.write("    pop     rbp");
+        self.write("    ret");
+        /* writes the digits of rax at rdi and moves rdi past them */
+        self.write(".append_u64:");
+        self.write("    lea     rcx, [rbp-1]");
+        self.write("    mov     r8, 10");
+        self.write(".append_digit:");
+        self.write("    xor     edx, edx");
+        self.write("    div     r8");
+        self.write("    add     dl, 48");
+        self.write("    mov     [rcx], dl");
+        self.write("    dec     rcx");
+        self.write("    test    rax, rax");
+        self.write("    jnz     .append_digit");
+        self.write(".append_copy:");
+        self.write("    inc     rcx");
+        self.write("    mov     dl, [rcx]");
+        self.write("    mov     [rdi], dl");
+        self.write("    inc     rdi");
+        self.write("    lea     rdx, [rbp-1]");
+        self.write("    cmp     rcx, rdx");
+        self.write("    jne     .append_copy");
+        self.write("    ret");
+        for (label, value) in [
+            ("f64_one", 1.0),
+            ("f64_ten", 10.0),
+            ("f64_million", 1e6),
+            ("f64_exponent_above", 1e12),
+            ("f64_exponent_below", 1e-4),
+        ] {
+            self.write_float_rodata(label, value);
+        }
+
+        /* print_str writes the string at rdi to stdout */
+        self.write("print_str:");
+        self.write("    mov     rdx, [rdi]");
+        self.write("    lea     rsi, [rdi+8]");
+        self.write("    mov     rax, 1");
+        self.write("    mov     rdi, 1");
+        self.write("    syscall");
+        self.write("    ret");
+
+        /* print_char writes the code point in rdi to stdout, encoded as utf-8 */
+        self.write("print_char:");
+        self.write("    sub     rsp, 8");
+        self.write("    mov     eax, edi");
+        self.write("    cmp     eax, 0x80");
+        self.write("    jae     .two_bytes");
+        self.write("    mov     [rsp], al");
+        self.write("    mov     edx, 1");
+        self.write("    jmp     .write");
+        self.write(".two_bytes:");
+        self.write("    cmp     eax, 0x800");
+        self.write("    jae     .three_bytes");
+        self.write("    mov     ecx, eax");
+        self.write("    shr     ecx, 6");
+        self.write("    or      cl, 0xc0");
+        self.write("    mov     [rsp], cl");
+        self.write("    mov     edx, 2");
+        self.write("    jmp     .last_byte");
+        self.write(".three_bytes:");
+        self.write("    cmp     eax, 0x10000");
+        self.write("    jae     .four_bytes");
+        self.write("    mov     ecx, eax");
+        self.write("    shr     ecx, 12");
+        self.write("    or      cl, 0xe0");
+        self.write("    mov     [rsp], cl");
+        self.write("    mov     edx, 3");
+        self.write("    jmp     .middle_byte");
+        self.write(".four_bytes:");
+        self.write("    mov     ecx, eax");
+        self.write("    shr     ecx, 18");
+        self.write("    or      cl, 0xf0");
+        self.write("    mov     [rsp], cl");
+        self.write("    mov     ecx, eax");
+        self.write("    shr     ecx, 12");
+        self.write("    and     cl, 0x3f");
+        self.write("    or      cl, 0x80");
+        self.write("    mov     [rsp+1], cl");
+        self.write("    mov     edx, 4");
+        /* the continuation bytes carry 6 bits each, the last one is at rsp+edx-1 */
+        self.write(".middle_byte:");
+        self.write("    mov     ecx, eax");
+        self.write("    shr     ecx, 6");
+        self.write("    and     cl, 0x3f");
+        self.write("    or      cl, 0x80");
+        self.write("    mov     [rsp+rdx-2], cl");
+        self.write(".last_byte:");
+        self.write("    and     al, 0x3f");
+        self.write("    or      al, 0x80");
+        self.write("    mov     [rsp+rdx-1], al");
+        self.write(".write:");
+        self.write("    mov     rax, 1");
+        self.write("    mov     rdi, 1");
+        self.write("    mov     rsi, rsp");
+        self.write("    syscall");
+        self.write("    add     rsp, 8");
+        self.write("    ret");
+
+        /* print ends every argument with one */
+        self.write("print_newline:");
+        self.write("    push    10");
+        self.write("    mov     rax, 1");
+        self.write("    mov     rdi, 1");
+        self.write("    mov     rsi, rsp");
+        self.write("    mov     rdx, 1");
+        self.write("    syscall");
+        self.write("    add     rsp, 8");
+        self.write("    ret");
+
+        /* rsi / rdx hold the message and its length, it goes to stderr and the program exits with 1 */
+        self.write("runtime_error:");
+        self.write("    mov     rax, 1");
+        self.write("    mov     rdi, 2");
+        self.write("    syscall");
+        self.write("    mov     rax, 60");
+        self.write("    mov     rdi, 1");
+        self.write("    syscall");
         self.write("global _start");
         self.write("_start:");
     }
 
     fn assemble_push(&mut self, number: impl std::fmt::Display) {
         self.write("    ;; -- push  --");
-        self.write(&*format!("    push {}", number))
+        self.write(&format!("    push {}", number))
+    }
+
+    /* push only takes a sign extended 32 bit immediate */
+    fn assemble_push_int(&mut self, number: i64) {
+        if i32::try_from(number).is_ok() {
+            self.assemble_push(number)
+        } else {
+            self.write("    ;; -- push  --");
+            self.write(&format!("    mov rax, {}", number));
+            self.write("    push rax")
+        }
     }
 
     fn assemble_plus(&mut self) {
@@ -121,129 +553,1001 @@ impl Compile {
         self.write("    ;; -- multiply --");
         self.write("    pop rax");
         self.write("    pop rbx");
-        self.write("    mul rbx");
+        self.write("    imul rax, rbx");
+        self.write("    push rax")
+    }
+
+    /*
+        idiv / div leave the quotient in rax and the remainder in rdx, result picks one. A divisor
+        of 0 is a runtime error pointing at span, and i64::MIN / -1 (which would trap as well) wraps.
+    */
+    fn assemble_divide(&mut self, name: &str, result: &str, signed: bool, span: Span) {
+        let message = format!(
+            "error: division by zero at {}:{}:{}\n",
+            self.file_name, span.start.line, span.start.col
+        );
+        let divide_label = self.new_label("divide");
+        let minus_one_label = self.new_label("divide_minus_one");
+        let done_label = self.new_label("divide_done");
+
+        self.write(&format!("    ;; -- {} --", name));
+        self.write("    pop rbx");
+        self.write("    pop rax");
+        self.write("    test rbx, rbx");
+        self.write(&format!("    jnz {}", divide_label));
+        self.assemble_runtime_error("division_by_zero", &message);
+        self.assemble_label(&divide_label);
+        if !signed {
+            self.write("    xor edx, edx");
+            self.write("    div rbx");
+            self.write(&format!("    push {}", result));
+            return;
+        }
+        self.write("    cmp rbx, -1");
+        self.write(&format!("    je {}", minus_one_label));
+        self.write("    cqo");
+        self.write("    idiv rbx");
+        self.assemble_jump(&done_label);
+
+        /* x / -1 is -x and x % -1 is 0 */
+        self.assemble_label(&minus_one_label);
+        self.write("    neg rax");
+        self.write("    xor edx, edx");
+        self.assemble_label(&done_label);
+        self.write(&format!("    push {}", result))
+    }
+
+    /* a step that is only known when the program runs is checked before the loop starts */
+    fn assemble_zero_step_check(&mut self, span: Span) {
+        let message = format!(
+            "error: for loop step is 0 at {}:{}:{}\n",
+            self.file_name, span.start.line, span.start.col
+        );
+        let step_label = self.new_label("for_step_ok");
+
+        self.write("    cmp QWORD [rsp], 0");
+        self.write(&format!("    jne {}", step_label));
+        self.assemble_runtime_error("zero_step", &message);
+        self.assemble_label(&step_label);
+    }
+
+    /* writes message to stderr and exits with 1 */
+    fn assemble_runtime_error(&mut self, name: &str, message: &str) {
+        let message_label = self.add_rodata(name, message.as_bytes());
+        self.write(&format!("    lea rsi, [rel {}]", message_label));
+        self.write(&format!("    mov rdx, {}", message.len()));
+        self.write("    jmp runtime_error");
+    }
+
+    /* bytes in .rodata under a fresh label, which is returned */
+    fn add_rodata(&mut self, name: &str, bytes: &[u8]) -> String {
+        let label = self.new_label(name);
+        let _ = writeln!(self.rodata, "{}:", label);
+        let _ = writeln!(self.rodata, "    db {}", nasm_bytes(bytes));
+        label
+    }
+
+    fn write_float_rodata(&mut self, label: &str, value: f64) {
+        let _ = writeln!(self.rodata, "{}:", label);
+        let _ = writeln!(self.rodata, "    dq 0x{:016x} ; {:?}", value.to_bits(), value);
+    }
+
+    /* f64 literals live in .rodata, push can't take them as an immediate */
+    fn assemble_push_float(&mut self, value: f64) {
+        let label = self.new_label("float");
+        self.write_float_rodata(&label, value);
+        self.write("    ;; -- push float --");
+        self.write(&format!("    push QWORD [rel {}]", label))
+    }
+
+    /* the length as a qword and then the bytes, identical literals share one label */
+    fn assemble_push_string(&mut self, text: String) {
+        let label = match self.strings.get(&text) {
+            Some(label) => label.clone(),
+            None => {
+                let label = self.new_label("string");
+                let _ = writeln!(self.rodata, "{}:", label);
+                let _ = writeln!(self.rodata, "    dq {}", text.len());
+                if !text.is_empty() {
+                    let _ = writeln!(self.rodata, "    db {}", nasm_bytes(text.as_bytes()));
+                }
+                self.strings.insert(text, label.clone());
+                label
+            }
+        };
+
+        self.write("    ;; -- push string --");
+        self.write(&format!("    lea rax, [rel {}]", label));
         self.write("    push rax")
     }
 
-    fn assemble_function(&mut self, name: String) {
-        match name.as_ref() {
-            "print" => {
-                self.write("    ;; -- dump --");
+    /* pops both sides into xmm0 (left) and xmm1 (right), an integer side is converted */
+    fn assemble_float_operands(&mut self, name: &str, left: Type, right: Type) {
+        self.write(&format!("    ;; -- float {} --", name));
+        self.write("    pop rax");
+        self.write("    pop rbx");
+        for (xmm, register, ty) in [("xmm0", "rbx", left), ("xmm1", "rax", right)] {
+            match ty {
+                Type::Int => self.write(&format!("    cvtsi2sd {}, {}", xmm, register)),
+                _ => self.write(&format!("    movq {}, {}", xmm, register)),
+            }
+        }
+    }
+
+    /*
+        Integers stay integers, and are unsigned as soon as one side is. With a float on one
+        side both are floats, except for unsigned integers which don't mix with floats.
+    */
+    fn assemble_arithmetic(
+        &mut self,
+        sides: (Type, Type),
+        name: &str,
+        instruction: &str,
+        span: Span,
+        assemble_int: impl FnOnce(&mut Self, bool),
+    ) -> Result<Type, CompileError> {
+        match sides {
+            (Type::Int, Type::Int) => {
+                assemble_int(self, true);
+                Ok(Type::Int)
+            }
+            (Type::Int | Type::UInt, Type::Int | Type::UInt) => {
+                assemble_int(self, false);
+                Ok(Type::UInt)
+            }
+            (Type::Char, _) | (_, Type::Char) => {
+                Err(CompileError::TypeMismatch { expected: Type::Int, found: Type::Char, span })
+            }
+            (Type::UInt, found) | (found, Type::UInt) => {
+                Err(CompileError::TypeMismatch { expected: Type::UInt, found, span })
+            }
+            (left, right) => {
+                self.assemble_float_operands(name, left, right);
+                self.write(&format!("    {} xmm0, xmm1", instruction));
+                self.write("    movq rax, xmm0");
+                self.write("    push rax");
+                Ok(Type::Float)
+            }
+        }
+    }
+
+    /*
+        cmov holds the signed and the unsigned condition. ucomisd reports NaN as unordered,
+        compare_float is written so that only != holds for it.
+    */
+    fn assemble_comparison(
+        &mut self,
+        sides: (Type, Type),
+        name: &str,
+        cmov: (&str, &str),
+        compare_float: &[&str],
+        span: Span,
+    ) -> Result<Type, CompileError> {
+        match sides {
+            (Type::Int, Type::Int) | (Type::Char, Type::Char) => self.assemble_compare(name, cmov.0),
+            (Type::Int | Type::UInt, Type::Int | Type::UInt) => self.assemble_compare(name, cmov.1),
+            (Type::Char, found) | (found, Type::Char) => {
+                return Err(CompileError::TypeMismatch { expected: Type::Char, found, span })
+            }
+            (Type::UInt, found) | (found, Type::UInt) => {
+                return Err(CompileError::TypeMismatch { expected: Type::UInt, found, span })
+            }
+            (left, right) => {
+                self.assemble_float_operands(name, left, right);
+                for line in compare_float {
+                    self.write(&format!("    {}", line));
+                }
+                self.write("    movzx rax, al");
+                self.write("    push rax");
+            }
+        }
+        Ok(Type::Int)
+    }
+
+    fn assemble_float_negate(&mut self) {
+        self.write("    ;; -- float negate --");
+        self.write("    pop rax");
+        self.write("    btc rax, 63");
+        self.write("    push rax")
+    }
+
+    /* the integer on top of the stack becomes a float */
+    fn assemble_int_to_float(&mut self) {
+        self.write("    ;; -- to float --");
+        self.write("    pop rax");
+        self.write("    cvtsi2sd xmm0, rax");
+        self.write("    movq rax, xmm0");
+        self.write("    push rax")
+    }
+
+    fn assemble_negate(&mut self) {
+        self.write("    ;; -- negate --");
+        self.write("    pop rax");
+        self.write("    neg rax");
+        self.write("    push rax")
+    }
+
+    fn assemble_not(&mut self) {
+        self.write("    ;; -- not --");
+        self.write("    pop rax");
+        self.write("    xor ecx, ecx");
+        self.write("    test rax, rax");
+        self.write("    sete cl");
+        self.write("    push rcx")
+    }
+
+    /* a fresh label like `if_end_3` */
+    fn new_label(&mut self, name: &str) -> String {
+        self.label_count += 1;
+        format!("{}_{}", name, self.label_count)
+    }
+
+    fn assemble_label(&mut self, label: &str) {
+        self.write(&format!("{}:", label))
+    }
+
+    fn assemble_jump(&mut self, label: &str) {
+        self.write(&format!("    jmp {}", label))
+    }
+
+    /* pops the condition and jumps when it's 0 */
+    fn assemble_jump_if_false(&mut self, label: &str) {
+        self.write("    ;; -- branch --");
+        self.write("    pop rax");
+        self.write("    test rax, rax");
+        self.write(&format!("    jz {}", label));
+        self.stack_depth -= 1
+    }
+
+    /* pops the condition and jumps when it isn't 0 */
+    fn assemble_jump_if_true(&mut self, label: &str) {
+        self.write("    ;; -- branch --");
+        self.write("    pop rax");
+        self.write("    test rax, rax");
+        self.write(&format!("    jnz {}", label));
+        self.stack_depth -= 1
+    }
+
+    /* drops everything pushed since the loop was entered and jumps to one of its labels */
+    fn assemble_loop_exit(&mut self, loop_index: usize, label: &str) {
+        let values = self.stack_depth - self.loops[loop_index].stack_depth;
+        if values > 0 {
+            self.write(&format!("    add rsp, {}", values * 8));
+        }
+        self.assemble_jump(label)
+    }
+
+    fn assemble_store(&mut self, storage: &Storage) {
+        self.write("    ;; -- store --");
+        self.write("    pop rax");
+        self.write(&format!("    mov {}, rax", storage.operand()));
+        self.stack_depth -= 1
+    }
+
+    /* the value of an expression statement isn't used */
+    fn assemble_drop(&mut self) {
+        self.write("    ;; -- drop --");
+        self.write("    add rsp, 8");
+        self.stack_depth -= 1
+    }
+
+    /* the arguments are on the stack, the last one on top. 16 byte aligned at the call as System V wants */
+    fn assemble_call(&mut self, name: &str, arg_count: usize) {
+        self.write(&format!("    ;; -- call {} --", name));
+        for (index, register) in ARGUMENT_REGISTERS.iter().enumerate().take(arg_count) {
+            self.write(&format!("    mov {}, [rsp+{}]", register, 8 * (arg_count - 1 - index)));
+        }
+
+        let stack_args = arg_count.saturating_sub(ARGUMENT_REGISTERS.len());
+        let padding = (self.stack_depth + stack_args) % 2;
+        if padding == 1 {
+            self.write("    sub rsp, 8");
+        }
+        for (pushed, index) in (ARGUMENT_REGISTERS.len()..arg_count).rev().enumerate() {
+            let offset = 8 * (arg_count - 1 - index + padding + pushed);
+            self.write(&format!("    push QWORD [rsp+{}]", offset));
+        }
+
+        self.write(&format!("    call {}", proc_label(name)));
+        self.write(&format!("    add rsp, {}", 8 * (arg_count + padding + stack_args)));
+        self.write("    push rax");
+        self.stack_depth = self.stack_depth + 1 - arg_count;
+    }
+
+    fn assemble_print(&mut self, ty: Type) {
+        self.write("    ;; -- print --");
+        match ty {
+            Type::Int => {
+                self.write("    pop rdi");
+                self.write("    call print_i64");
+            }
+            Type::UInt => {
+                self.write("    pop rdi");
+                self.write("    call print_u64");
+            }
+            Type::Float => {
+                self.write("    pop rax");
+                self.write("    movq xmm0, rax");
+                self.write("    call print_f64");
+            }
+            Type::Char => {
+                self.write("    pop rdi");
+                self.write("    call print_char");
+            }
+            Type::Str => {
                 self.write("    pop rdi");
-                self.write("    call dump") 
+                self.write("    call print_str");
             }
-            _ => {}
         }
+        self.stack_depth -= 1
     }
 
-    fn assemble_equal(&mut self){
-        self.write("    ;; -- equal --");
+    /* prints expr without a newline, an interpolated string is printed part by part */
+    fn compile_print_part(&mut self, expr: Expr) -> Result<(), CompileError> {
+        match expr {
+            Expr::OpExpr(op_expr) if matches!(op_expr.op, Operator::Concat | Operator::Format) => {
+                op_expr.args.into_iter().try_for_each(|part| self.compile_print_part(part))
+            }
+            expr => {
+                let ty = self.evaluate(expr)?;
+                self.assemble_print(ty);
+                Ok(())
+            }
+        }
+    }
+
+    /* pushes 1 when `left cmp right` holds, cmov picks the condition */
+    fn assemble_compare(&mut self, name: &str, cmov: &str){
+        self.write(&format!("    ;; -- {} --", name));
         self.write("    mov rcx, 0");
         self.write("    mov rdx, 1");
         self.write("    pop rax");
         self.write("    pop rbx");
-        self.write("    cmp rax, rbx");
-        self.write("    cmove rcx, rdx");
+        self.write("    cmp rbx, rax");
+        self.write(&format!("    {} rcx, rdx", cmov));
         self.write("    push rcx");
     }
 
-    fn evaluate_both_sides(&mut self, args: Vec<Expr>) {
-        self.evaluate(args[0].clone());
-        self.evaluate(args[1].clone());
+
+    /* both sides of arithmetic and comparisons, which are numbers */
+    fn evaluate_both_sides(&mut self, args: Vec<Expr>) -> Result<(Type, Type), CompileError> {
+        let left = self.evaluate_number(args[0].clone())?;
+        let right = self.evaluate_number(args[1].clone())?;
+        Ok((left, right))
     }
 
-    pub fn evaluate(&mut self, expr: Expr) {
-        match expr {
-            Expr::OpExpr(bx_expr) => match *bx_expr {
-                OpExpr { op, args } => match op {
+    fn evaluate_number(&mut self, expr: Expr) -> Result<Type, CompileError> {
+        let span = expr.span();
+        match self.evaluate(expr)? {
+            Type::Str => Err(CompileError::TypeMismatch { expected: Type::Int, found: Type::Str, span }),
+            ty => Ok(ty),
+        }
+    }
+
+    /*
+        `&&` stops at the first side that is 0 and `||` at the first that isn't, so the right
+        side only runs when it decides the result. The result is 0 or 1.
+    */
+    fn evaluate_logical(&mut self, args: Vec<Expr>, name: &str) -> Result<Type, CompileError> {
+        let short_label = self.new_label(&format!("{}_short", name));
+        let end_label = self.new_label(&format!("{}_end", name));
+        let short_value = if name == "and" { 0 } else { 1 };
+
+        for arg in args {
+            self.evaluate_int(arg)?;
+            if name == "and" {
+                self.assemble_jump_if_false(&short_label);
+            } else {
+                self.assemble_jump_if_true(&short_label);
+            }
+        }
+
+        self.assemble_push(1 - short_value);
+        self.assemble_jump(&end_label);
+        self.assemble_label(&short_label);
+        self.assemble_push(short_value);
+        self.assemble_label(&end_label);
+        Ok(Type::Int)
+    }
+
+    /* conditions, counters, arguments and the like are integers */
+    fn evaluate_int(&mut self, expr: Expr) -> Result<(), CompileError> {
+        let span = expr.span();
+        match self.evaluate(expr)? {
+            Type::Int => Ok(()),
+            found => Err(CompileError::TypeMismatch { expected: Type::Int, found, span }),
+        }
+    }
+
+    pub fn compile_statement(&mut self, stmt: Stmt) -> Result<(), CompileError> {
+        match stmt {
+            /* the value is computed before the name exists, so `x := x + 1` reads the outer x */
+            Stmt::Let { name, value, .. } => {
+                let ty = self.evaluate(value)?;
+                let storage = self.declare(&name, ty);
+                self.assemble_store(&storage)
+            }
+            /*
+                assigning to a name that doesn't exist yet declares it. A variable keeps its type,
+                an integer stored in a float is converted but a float doesn't fit in an integer
+            */
+            Stmt::Assign { name, value, .. } => {
+                let span = value.span();
+                let ty = self.evaluate(value)?;
+                let storage = match self.lookup(&name) {
+                    Some(variable) => {
+                        match (variable.ty, ty) {
+                            (Type::Float, Type::Int) => self.assemble_int_to_float(),
+                            (expected, found) if expected != found => {
+                                return Err(CompileError::TypeMismatch { expected, found, span })
+                            }
+                            _ => {}
+                        }
+                        variable.storage
+                    }
+                    None => self.declare(&name, ty),
+                };
+                self.assemble_store(&storage)
+            }
+            Stmt::Expr(expr) => {
+                self.evaluate(expr)?;
+
+                self.assemble_drop()
+            }
+            Stmt::If { condition, then_block, else_block, .. } => {
+                let else_label = self.new_label("if_else");
+                let end_label = self.new_label("if_end");
+
+                self.evaluate_int(condition)?;
+                self.assemble_jump_if_false(&else_label);
+
+                self.compile_block(then_block)?;
+                self.assemble_jump(&end_label);
+
+                self.assemble_label(&else_label);
+                if let Some(else_block) = else_block {
+                    self.compile_block(else_block)?;
+                }
+                self.assemble_label(&end_label)
+            }
+            Stmt::While { label, condition, body, .. } => {
+                let condition_label = self.new_label("while_condition");
+                let end_label = self.new_label("while_end");
+
+                self.assemble_label(&condition_label);
+                self.evaluate_int(condition)?;
+                self.assemble_jump_if_false(&end_label);
+
+                self.compile_loop_body(label, &condition_label, &end_label, body)?;
+                self.assemble_jump(&condition_label);
+                self.assemble_label(&end_label)
+            }
+            Stmt::For { label, variable, start, end, inclusive, step, body, .. } => {
+                let condition_label = self.new_label("for_condition");
+                let down_label = self.new_label("for_down");
+                let body_label = self.new_label("for_body");
+                let step_label = self.new_label("for_step");
+                let done_label = self.new_label("for_done");
+
+                /*
+                    The range is evaluated in the order it's written. start, end and step stay on
+                    the stack for the whole loop, end at [rsp+8] and step at [rsp]
+                */
+                self.evaluate_int(start)?;
+                self.evaluate_int(end)?;
+                match step {
+                    Some(Expr::OpLiteral(literal, span)) if matches!(*literal, Literal::Integer(0)) => {
+                        return Err(CompileError::ZeroStep { span });
+                    }
+                    Some(step) => {
+                        let span = step.span();
+                        self.evaluate_int(step)?;
+                        self.assemble_zero_step_check(span);
+                    }
+                    None => {
+                        self.assemble_push(1);
+                        self.stack_depth += 1;
+                    }
+                }
+
+                /* the counter gets the next free register, and a variable once they're all taken */
+                self.scopes.push(HashMap::new());
+                let register = COUNTER_REGISTERS.get(self.counters).copied();
+                let counter = match register {
+                    Some(register) => {
+                        self.counters += 1;
+                        if let Some(frame) = &mut self.frame {
+                            frame.counters_used = frame.counters_used.max(self.counters);
+                        }
+                        let storage = Storage::Register(register);
+                        if let Some(scope) = self.scopes.last_mut() {
+                            scope.insert(variable, Variable { storage: storage.clone(), ty: Type::Int });
+                        }
+                        storage
+                    }
+                    None => self.declare(&variable, Type::Int),
+                }
+                .operand();
+                self.write("    mov rax, [rsp+16]");
+                self.write(&format!("    mov {}, rax", counter));
+
+                /* a negative step counts down towards end */
+                let (up_exit, down_exit) = if inclusive { ("jg", "jl") } else { ("jge", "jle") };
+                self.assemble_label(&condition_label);
+                self.write("    mov rax, [rsp+8]");
+                self.write("    cmp QWORD [rsp], 0");
+                self.write(&format!("    jl {}", down_label));
+                self.write(&format!("    cmp {}, rax", counter));
+                self.write(&format!("    {} {}", up_exit, done_label));
+                self.assemble_jump(&body_label);
+                self.assemble_label(&down_label);
+                self.write(&format!("    cmp {}, rax", counter));
+                self.write(&format!("    {} {}", down_exit, done_label));
+
+                self.assemble_label(&body_label);
+                let compiled = self.compile_loop_body(label, &step_label, &done_label, body);
+                self.scopes.pop();
+                if register.is_some() {
+                    self.counters -= 1;
+                }
+                compiled?;
+
+                /* stepping past the largest or smallest i64 also leaves the range */
+                self.assemble_label(&step_label);
+                self.write("    mov rax, [rsp]");
+                self.write(&format!("    add {}, rax", counter));
+                self.write(&format!("    jo {}", done_label));
+                self.assemble_jump(&condition_label);
+
+                self.assemble_label(&done_label);
+                self.write("    add rsp, 24");
+                self.stack_depth -= 3
+            }
+            Stmt::Return { value, .. } => {
+                match value {
+                    Some(value) => {
+                        self.evaluate_int(value)?;
+                        self.write("    pop rax");
+                        self.stack_depth -= 1;
+                    }
+                    None => self.write("    xor eax, eax"),
+                }
+
+                /* the epilogue resets rsp, so whatever is still pushed doesn't matter */
+                let return_label = self.frame.as_ref().map(|frame| frame.return_label.clone());
+                self.assemble_jump(&return_label.expect("checked by the parser"))
+            }
+            Stmt::Proc { name, params, body, span } => {
+                if self.block_depth > 0 {
+                    let what = "defining procedures inside blocks".to_string();
+                    return Err(CompileError::Unsupported { what, span });
+                }
+
+                /* a second definition of the name was already reported by declare_procs */
+                if matches!(self.procs.get(&name), Some((_, defined)) if *defined == span) {
+                    self.compile_proc(&name, params, body)?
+                }
+            }
+            Stmt::Break { label, value, .. } => {
+                /* the value travels in rax, loop pushes it once it's out */
+                match value {
+                    Some(value) => {
+                        self.evaluate_int(value)?;
+                        self.write("    pop rax");
+                        self.stack_depth -= 1;
+                    }
+                    None => self.write("    xor eax, eax"),
+                }
+
+                let index = self.find_loop(label.as_deref());
+                let break_label = self.loops[index].break_label.clone();
+                self.assemble_loop_exit(index, &break_label)
+            }
+            Stmt::Continue { label, .. } => {
+                let index = self.find_loop(label.as_deref());
+                let continue_label = self.loops[index].continue_label.clone();
+                self.assemble_loop_exit(index, &continue_label)
+            }
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> Option<Variable> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name)).cloned()
+    }
+
+    /* a new variable in the innermost scope, in the frame inside procedures and in .bss otherwise */
+    fn declare(&mut self, name: &str, ty: Type) -> Storage {
+        let storage = match &mut self.frame {
+            Some(frame) => {
+                frame.slot_count += 1;
+                Storage::Slot(frame.slot_count)
+            }
+            None => {
+                self.label_count += 1;
+                let label = format!("{}_{}", mangle("global", name), self.label_count);
+                let _ = writeln!(self.bss, "{}: resq 1", label);
+                Storage::Global(label)
+            }
+        };
+
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), Variable { storage: storage.clone(), ty });
+        }
+        storage
+    }
+
+    /* registers every top level procedure, so calls can come before the definition */
+    pub fn declare_procs(&mut self, stmts: &[Stmt]) -> Vec<CompileError> {
+        let mut errors = vec![];
+
+        for stmt in stmts {
+            if let Stmt::Proc { name, params, span, .. } = stmt {
+                if self.procs.contains_key(name) {
+                    errors.push(CompileError::DuplicateProc { name: name.clone(), span: *span });
+                } else {
+                    self.procs.insert(name.clone(), (params.len(), *span));
+                }
+            }
+        }
+
+        errors
+    }
+
+    /*
+        The body is compiled first, into its own buffer, so the prologue knows how much of a
+        frame it needs. Parameters are copied into the frame, a procedure that ends without
+        a return returns 0.
+    */
+    fn compile_proc(&mut self, name: &str, params: Vec<String>, body: Block) -> Result<(), CompileError> {
+        let label = proc_label(name);
+        let param_count = params.len();
+
+        let outer_asm = std::mem::take(&mut self.asm);
+        let outer_stack_depth = std::mem::replace(&mut self.stack_depth, 0);
+        let outer_loops = std::mem::take(&mut self.loops);
+        let outer_counters = std::mem::replace(&mut self.counters, 0);
+
+        /* the main program's globals stay visible, its for loop counters don't */
+        let globals = self.scopes[0].clone();
+        let parameters = params
+            .into_iter()
+            .enumerate()
+            .map(|(index, param)| (param, Variable { storage: Storage::Slot(index + 1), ty: Type::Int }))
+            .collect();
+        let outer_scopes = std::mem::replace(&mut self.scopes, vec![globals, parameters]);
+        self.frame = Some(Frame { slot_count: param_count, return_label: format!("{}_return", label), counters_used: 0 });
+
+        let compiled = self.compile_block(body);
+
+        let body_asm = std::mem::replace(&mut self.asm, outer_asm);
+        let frame = self.frame.take().expect("set above");
+        self.stack_depth = outer_stack_depth;
+        self.loops = outer_loops;
+        self.counters = outer_counters;
+        self.scopes = outer_scopes;
+        compiled?;
+
+        let saved_counters = &COUNTER_REGISTERS[..frame.counters_used];
+        let slot_count = frame.slot_count + saved_counters.len();
+        let frame_size = 8 * (slot_count + slot_count % 2);
+
+        let functions = &mut self.functions;
+        let _ = writeln!(functions, "{}:", label);
+        let _ = writeln!(functions, "    push rbp");
+        let _ = writeln!(functions, "    mov rbp, rsp");
+        if frame_size > 0 {
+            let _ = writeln!(functions, "    sub rsp, {}", frame_size);
+        }
+        for index in 0..param_count {
+            match ARGUMENT_REGISTERS.get(index) {
+                Some(register) => {
+                    let _ = writeln!(functions, "    mov [rbp-{}], {}", 8 * (index + 1), register);
+                }
+                None => {
+                    let _ = writeln!(functions, "    mov rax, [rbp+{}]", 16 + 8 * (index - ARGUMENT_REGISTERS.len()));
+                    let _ = writeln!(functions, "    mov [rbp-{}], rax", 8 * (index + 1));
+                }
+            }
+        }
+        for (index, register) in saved_counters.iter().enumerate() {
+            let _ = writeln!(functions, "    mov [rbp-{}], {}", 8 * (frame.slot_count + index + 1), register);
+        }
+
+        functions.push_str(&body_asm);
+
+        let _ = writeln!(functions, "    xor eax, eax");
+        let _ = writeln!(functions, "{}:", frame.return_label);
+        for (index, register) in saved_counters.iter().enumerate() {
+            let _ = writeln!(functions, "    mov {}, [rbp-{}]", register, 8 * (frame.slot_count + index + 1));
+        }
+        let _ = writeln!(functions, "    mov rsp, rbp");
+        let _ = writeln!(functions, "    pop rbp");
+        let _ = writeln!(functions, "    ret");
+
+        Ok(())
+    }
+
+    fn compile_block(&mut self, block: Block) -> Result<(), CompileError> {
+        self.block_depth += 1;
+        self.scopes.push(HashMap::new());
+        let compiled = block.stmts.into_iter().try_for_each(|stmt| self.compile_statement(stmt));
+        self.scopes.pop();
+        self.block_depth -= 1;
+        compiled
+    }
+
+    /* the parser made sure break and continue are inside a loop with that label */
+    fn find_loop(&self, label: Option<&str>) -> usize {
+        let found = match label {
+            Some(label) => self.loops.iter().rposition(|l| l.label.as_deref() == Some(label)),
+            None => self.loops.len().checked_sub(1),
+        };
+        found.expect("checked by the parser")
+    }
+
+    fn compile_loop_body(&mut self, label: Option<String>, continue_label: &str, break_label: &str, body: Block) -> Result<(), CompileError> {
+        self.loops.push(LoopLabels {
+            label,
+            continue_label: continue_label.to_string(),
+            break_label: break_label.to_string(),
+            stack_depth: self.stack_depth,
+        });
+        let compiled = self.compile_block(body);
+        self.loops.pop();
+        compiled
+    }
+
+    /* every expression leaves exactly one value on the stack */
+    pub fn evaluate(&mut self, expr: Expr) -> Result<Type, CompileError> {
+        let stack_depth = self.stack_depth;
+        let ty = self.evaluate_value(expr)?;
+        self.stack_depth = stack_depth + 1;
+        Ok(ty)
+    }
+
+    fn evaluate_value(&mut self, expr: Expr) -> Result<Type, CompileError> {
+        let ty = match expr {
+            Expr::OpExpr(bx_expr) => {
+                let OpExpr { op, args, span } = *bx_expr;
+                match op {
                     Operator::Plus => {
-                        self.evaluate_both_sides(args);
+                        let sides = self.evaluate_both_sides(args)?;
 
-                        self.assemble_plus()
+                        self.assemble_arithmetic(sides, "plus", "addsd", span, |comp, _| comp.assemble_plus())?
                     }
                     Operator::Substract => {
-                        self.evaluate_both_sides(args);
+                        let sides = self.evaluate_both_sides(args)?;
 
-                        self.assemble_minus()
+                        self.assemble_arithmetic(sides, "minus", "subsd", span, |comp, _| comp.assemble_minus())?
                     }
                     Operator::Multiply => {
-                        self.evaluate_both_sides(args);
+                        let sides = self.evaluate_both_sides(args)?;
+
+                        self.assemble_arithmetic(sides, "multiply", "mulsd", span, |comp, _| comp.assemble_multiply())?
+                    }
+                    /* dividing floats by zero gives inf or NaN, only integers check for it */
+                    Operator::Division => {
+                        let sides = self.evaluate_both_sides(args)?;
 
-                        self.assemble_multiply()
+                        self.assemble_arithmetic(sides, "divide", "divsd", span, |comp, signed| {
+                            comp.assemble_divide("divide", "rax", signed, span)
+                        })?
                     }
+                    Operator::Modulos => match self.evaluate_both_sides(args)? {
+                        (Type::Float, _) | (_, Type::Float) => {
+                            return Err(CompileError::TypeMismatch { expected: Type::Int, found: Type::Float, span })
+                        }
+                        sides => self.assemble_arithmetic(sides, "modulo", "", span, |comp, signed| {
+                            comp.assemble_divide("modulo", "rdx", signed, span)
+                        })?,
+                    },
                     Operator::Equal => {
-                        self.evaluate_both_sides(args);
+                        let sides = self.evaluate_both_sides(args)?;
+
+                        self.assemble_comparison(sides, "equal", ("cmove", "cmove"), &["ucomisd xmm0, xmm1", "sete al", "setnp cl", "and al, cl"], span)?
+                    }
+                    Operator::NotEqual => {
+                        let sides = self.evaluate_both_sides(args)?;
+
+                        self.assemble_comparison(sides, "not equal", ("cmovne", "cmovne"), &["ucomisd xmm0, xmm1", "setne al", "setp cl", "or al, cl"], span)?
+                    }
+                    Operator::LessThan => {
+                        let sides = self.evaluate_both_sides(args)?;
 
-                        self.assemble_equal()
+                        self.assemble_comparison(sides, "less than", ("cmovl", "cmovb"), &["ucomisd xmm1, xmm0", "seta al"], span)?
+                    }
+                    Operator::GreaterThan => {
+                        let sides = self.evaluate_both_sides(args)?;
+
+                        self.assemble_comparison(sides, "greater than", ("cmovg", "cmova"), &["ucomisd xmm0, xmm1", "seta al"], span)?
+                    }
+                    Operator::LessThanOrEqual => {
+                        let sides = self.evaluate_both_sides(args)?;
+
+                        self.assemble_comparison(sides, "less than or equal", ("cmovle", "cmovbe"), &["ucomisd xmm1, xmm0", "setae al"], span)?
+                    }
+                    Operator::GreaterThantOrEqual => {
+                        let sides = self.evaluate_both_sides(args)?;
+
+                        self.assemble_comparison(sides, "greater than or equal", ("cmovge", "cmovae"), &["ucomisd xmm0, xmm1", "setae al"], span)?
+                    }
+                    Operator::BooleanAnd => self.evaluate_logical(args, "and")?,
+                    Operator::BooleanOr => self.evaluate_logical(args, "or")?,
+                    Operator::UnaryPass => {
+                        self.evaluate(args[0].clone())?
+                    }
+                    Operator::UnaryMinus => {
+                        let ty = self.evaluate(args[0].clone())?;
+
+                        match ty {
+                            Type::Int => self.assemble_negate(),
+                            Type::Float => self.assemble_float_negate(),
+                            Type::UInt | Type::Char | Type::Str => {
+                                let span = args[0].span();
+                                return Err(CompileError::TypeMismatch { expected: Type::Int, found: ty, span });
+                            }
+                        }
+                        ty
                     }
+                    Operator::LogicalNegate => {
+                        self.evaluate_int(args[0].clone())?;
+
+                        self.assemble_not();
+                        Type::Int
+                    }
+                    Operator::Call(name) if name == "print" => {
+                        for arg in args {
+                            self.compile_print_part(arg)?;
+
+                            self.write("    call print_newline");
+                        }
+
+                        /* every expression leaves one value behind, print's is 0 */
+                        self.assemble_push(0);
+                        Type::Int
+                    }
+                    /* procedures take and return integers, in the integer registers */
                     Operator::Call(name) => {
-                        self.evaluate(args[0].clone());
+                        let expected = match self.procs.get(&name) {
+                            Some((expected, _)) => *expected,
+                            None => return Err(CompileError::UnknownProc { name, span }),
+                        };
+                        if expected != args.len() {
+                            return Err(CompileError::ArgumentCount { name, expected, found: args.len(), span });
+                        }
+
+                        let arg_count = args.len();
+                        for arg in args {
+                            self.evaluate_int(arg)?;
+                        }
 
-                        self.assemble_function(name)
+                        self.assemble_call(&name, arg_count);
+                        Type::Int
                     }
-                    _ => {}
+                    /* strings are only built up while printing them, there is no heap to hold the result */
+                    Operator::Concat | Operator::Format => {
+                        let what = "string interpolation outside of `print`".to_string();
+                        return Err(CompileError::Unsupported { what, span });
+                    }
+                    op => {
+                        return Err(CompileError::Unsupported { what: op.to_string(), span })
+                    }
+                }
+            }
+            Expr::Loop(loop_expr) => {
+                let LoopExpr { label, body, .. } = *loop_expr;
+                let start_label = self.new_label("loop_start");
+                let end_label = self.new_label("loop_end");
+
+                self.assemble_label(&start_label);
+                self.compile_loop_body(label, &start_label, &end_label, body)?;
+                self.assemble_jump(&start_label);
+
+                self.assemble_label(&end_label);
+                self.write("    push rax");
+                Type::Int
+            }
+            Expr::OpLiteral(bx_lit, span) => match *bx_lit {
+                Literal::Integer(int_val) => {
+                    self.assemble_push_int(int_val);
+                    Type::Int
+                }
+                Literal::Unsigned(int_val) => {
+                    self.assemble_push_int(int_val as i64);
+                    Type::UInt
+                }
+                Literal::FloatingPoint(float_val) => {
+                    self.assemble_push_float(float_val);
+                    Type::Float
+                }
+                Literal::Symbol(name) => match self.lookup(&name) {
+                    Some(variable) => {
+                        self.write(&format!("    push {}", variable.storage.operand()));
+                        variable.ty
+                    }
+                    None => return Err(CompileError::UndefinedVariable { name, span }),
                 },
+                Literal::String(string_val) => {
+                    self.assemble_push_string(string_val);
+                    Type::Str
+                }
+                Literal::Boolean(bool_val) => {
+                    self.assemble_push(bool_val as i64);
+                    Type::Int
+                }
+                Literal::Char(char_val) => {
+                    self.assemble_push(char_val as u32);
+                    Type::Char
+                }
             },
-            Expr::OpLiteral(bx_lit) => match *bx_lit {
-                Literal::Integer(int_val) => self.assemble_push(int_val),
-                Literal::FloatingPoint(float_val) => self.assemble_push(float_val),
-                _ => {}
-            },
-            _=> {},
+        };
+        Ok(ty)
+    }
+
+    /* runs the external tool, stdout of the tool is returned */
+    fn run_tool(tool: &str, args: &[&str]) -> Result<String, CompileError> {
+        let output = Command::new(tool).args(args).output().map_err(|e| CompileError::Toolchain {
+            tool: tool.to_string(),
+            message: format!("couldn't be executed, {}", e),
+        })?;
+
+        if !output.status.success() {
+            return Err(CompileError::Toolchain {
+                tool: tool.to_string(),
+                message: format!("{}, {}", output.status, String::from_utf8_lossy(&output.stderr).trim()),
+            });
         }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
     }
 
-    pub fn execute(&mut self) {
+    pub fn execute(&mut self) -> Result<(), CompileError> {
         self.write("    mov rax, 60");
         self.write("    mov rdi, 0");
         self.write("    syscall");
+        let functions = std::mem::take(&mut self.functions);
+        self.asm.push_str(&functions);
+        if !self.rodata.is_empty() {
+            let rodata = std::mem::take(&mut self.rodata);
+            self.write("segment .rodata");
+            self.asm.push_str(&rodata);
+        }
+        if !self.bss.is_empty() {
+            let bss = std::mem::take(&mut self.bss);
+            self.write("segment .bss");
+            self.asm.push_str(&bss);
+        }
 
-        let nasm_output = Command::new("nasm")
-            .arg("-felf64")
-            .arg("./output/output.asm")
-            .output();
-
-        match nasm_output {
-            Ok(output) => {
-                if output.status.success() {
-                    let ld_output = Command::new("ld")
-                        .arg("-o")
-                        .arg("./output/output")
-                        .arg("./output/output.o")
-                        .output();
-
-                    match ld_output {
-                        Ok(output) => {
-                            if output.status.success() {
-                                let executable_output = Command::new("./output/output")
-                                    .output()
-                                    .expect("Failed to run the executable");
-
-                                let stdout = String::from_utf8_lossy(&executable_output.stdout);
-
-                                println!("{}", stdout);
-
-                                if !executable_output.status.success() {
-                                    eprintln!(
-                                        "Executable failed with error: {:?}",
-                                        executable_output.status
-                                    );
-                                }
-                            } else {
-                                eprintln!("ld command failed with error: {:?}", output.status);
-                            }
-                        }
-                        Err(err) => {
-                            eprintln!("Error executing ld command: {:?}", err);
-                        }
-                    }
-                } else {
-                    eprintln!("nasm command failed with error: {:?}", output.status);
-                }
-            }
-            Err(err) => {
-                eprintln!("Error executing nasm command: {:?}", err);
-            }
+        let asm_path = format!("{}/output.asm", self.output_path);
+        let object_path = format!("{}/output.o", self.output_path);
+        let binary_path = format!("{}/output", self.output_path);
+
+        fs::create_dir_all(&self.output_path)
+            .and_then(|_| fs::write(&asm_path, &self.asm))
+            .map_err(|source| CompileError::Io { path: asm_path.clone(), source })?;
+
+        Compile::run_tool("nasm", &["-felf64", &asm_path, "-o", &object_path])?;
+        Compile::run_tool("ld", &["-o", &binary_path, &object_path])?;
+
+        let executable_output = Command::new(&binary_path).output().map_err(|e| CompileError::Toolchain {
+            tool: binary_path.clone(),
+            message: format!("couldn't be executed, {}", e),
+        })?;
+
+        let stdout = String::from_utf8_lossy(&executable_output.stdout);
+
+        println!("{}", stdout);
+
+        /* runtime errors, like a division by zero, are written to stderr */
+        eprint!("{}", String::from_utf8_lossy(&executable_output.stderr));
+
+        if !executable_output.status.success() {
+            eprintln!(
+                "Executable failed with error: {}",
+                executable_output.status
+            );
         }
+        Ok(())
     }
 }
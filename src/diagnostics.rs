@@ -0,0 +1,298 @@
+use std::fmt::Write;
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::compiler::{CompileError, Type};
+use crate::lexer::*;
+use crate::parser::ParserError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+        }
+    }
+}
+
+/* a single message for the user, rendered against the source it points into */
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            span: None,
+            notes: vec![],
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Diagnostic {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_help(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    /*
+        error[E0201]: Was expecting an expression, found `)`
+         --> examples/test.df:1:11
+          |
+        1 | print(1 + )
+          |           ^
+          = help: ...
+    */
+    pub fn render(&self, file_name: &str, source: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{}[{}]: {}", self.severity.as_str(), self.code, self.message);
+
+        match self.span {
+            Some(span) => {
+                let line_text = source.lines().nth(span.start.line - 1).unwrap_or("");
+                let gutter = " ".repeat(span.start.line.to_string().len());
+
+                let _ = writeln!(out, "{}--> {}:{}:{}", gutter, file_name, span.start.line, span.start.col);
+                let _ = writeln!(out, "{} |", gutter);
+                let _ = writeln!(out, "{} | {}", span.start.line, line_text);
+
+                /* spans over multiple lines are underlined up to the end of the first one */
+                let line_len = line_text.chars().count();
+                let end_col = if span.end.line == span.start.line {
+                    span.end.col
+                } else {
+                    line_len + 1
+                };
+                let width: usize = line_text
+                    .chars()
+                    .chain(std::iter::repeat(' '))
+                    .skip(span.start.col - 1)
+                    .take(end_col.saturating_sub(span.start.col))
+                    .map(|c| c.width().unwrap_or(0))
+                    .sum();
+
+                /*
+                    the padding is as wide as the text before the span, combining marks like a
+                    virama take no room. Tabs are kept, so the caret lines up however wide the
+                    terminal shows them
+                */
+                let padding: String = line_text
+                    .chars()
+                    .chain(std::iter::repeat(' '))
+                    .take(span.start.col - 1)
+                    .map(|c| if c == '\t' { "\t".to_string() } else { " ".repeat(c.width().unwrap_or(0)) })
+                    .collect();
+
+                let _ = writeln!(out, "{} | {}{}", gutter, padding, "^".repeat(width.max(1)));
+
+                for note in &self.notes {
+                    let _ = writeln!(out, "{} = help: {}", gutter, note);
+                }
+            }
+            None => {
+                let _ = writeln!(out, " --> {}", file_name);
+
+                for note in &self.notes {
+                    let _ = writeln!(out, " = help: {}", note);
+                }
+            }
+        }
+
+        out
+    }
+}
+
+impl Diagnostic {
+    /* one line of json, for editors and CI to consume */
+    pub fn to_json(&self, file_name: &str) -> String {
+        let span = match self.span {
+            Some(span) => format!(
+                "{{\"file\":{},\"line\":{},\"column\":{},\"end_line\":{},\"end_column\":{},\"byte_start\":{},\"byte_end\":{}}}",
+                json_string(file_name),
+                span.start.line,
+                span.start.col,
+                span.end.line,
+                span.end.col,
+                span.start.offset,
+                span.end.offset
+            ),
+            None => "null".to_string(),
+        };
+
+        let notes: Vec<String> = self.notes.iter().map(|note| json_string(note)).collect();
+
+        format!(
+            "{{\"severity\":{},\"code\":{},\"message\":{},\"span\":{},\"notes\":[{}]}}",
+            json_string(self.severity.as_str()),
+            json_string(self.code),
+            json_string(&self.message),
+            span,
+            notes.join(",")
+        )
+    }
+}
+
+fn json_string(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len() + 2);
+    out.push('"');
+    for c in raw.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl From<&LexerError> for Diagnostic {
+    fn from(error: &LexerError) -> Diagnostic {
+        match error {
+            LexerError::FileIo(_) => Diagnostic::error("E0100", error.to_string()),
+            LexerError::NumericInvalid { span, .. } => Diagnostic::error("E0102", error.to_string())
+                .with_span(*span)
+                .with_help("numbers look like 42, 1_000, 0xff, 0b1010, 0o755, 1.5e-3 or 10i64"),
+            LexerError::MisbalancedBraces { open, span, .. } => Diagnostic::error("E0103", error.to_string())
+                .with_span(*span)
+                .with_help(format!("remove it or add an opening `{}` before it", open)),
+            LexerError::UnknownSymbol { span, .. } => {
+                Diagnostic::error("E0104", error.to_string()).with_span(*span)
+            }
+            LexerError::InvalidEscape { span, .. } => Diagnostic::error("E0107", error.to_string())
+                .with_span(*span)
+                .with_help("valid escapes are \\n \\t \\r \\0 \\\\ \\' \\\" \\xNN and \\u{...}, or use a raw string r\"...\""),
+            LexerError::InvalidCharLiteral { span, .. } => Diagnostic::error("E0106", error.to_string())
+                .with_span(*span)
+                .with_help("use double quotes for strings, e.g. \"hello\""),
+            LexerError::UnclosedInterpolation { span } => Diagnostic::error("E0110", error.to_string())
+                .with_span(*span)
+                .with_help("close the expression with `}`, or write `{{` for a literal brace"),
+            LexerError::UnmatchedInterpolationBrace { span } => Diagnostic::error("E0111", error.to_string())
+                .with_span(*span)
+                .with_help("write `}}` for a literal brace"),
+            LexerError::UnterminatedBlockComment { span } => Diagnostic::error("E0108", error.to_string())
+                .with_span(*span)
+                .with_help("close it with `]#`, block comments nest so every `#[` needs one"),
+            LexerError::UnknownKeywords { span, .. } => Diagnostic::error("E0109", error.to_string())
+                .with_span(*span)
+                .with_help("the keyword sets are `english` and `nepali`"),
+            LexerError::UnexpectedEOF { span } => Diagnostic::error("E0105", error.to_string())
+                .with_span(*span)
+                .with_help("a string might be missing its closing quote"),
+        }
+    }
+}
+
+impl From<&ParserError> for Diagnostic {
+    fn from(error: &ParserError) -> Diagnostic {
+        match error {
+            ParserError::UnexpectedToken { found, .. } => {
+                Diagnostic::error("E0201", error.to_string()).with_span(found.span)
+            }
+            ParserError::UnclosedDelimiter { close, span, .. } => Diagnostic::error("E0202", error.to_string())
+                .with_span(*span)
+                .with_help(format!("add a matching `{}`", close)),
+            ParserError::MissingArgumentList { name, span } => Diagnostic::error("E0203", error.to_string())
+                .with_span(*span)
+                .with_help(format!("arguments go in parentheses, e.g. `{}(10)`", name)),
+            ParserError::InvalidLiteral { span, .. } => {
+                Diagnostic::error("E0204", error.to_string()).with_span(*span)
+            }
+            ParserError::InvalidAssignmentTarget { span } => Diagnostic::error("E0205", error.to_string())
+                .with_span(*span)
+                .with_help("assignments look like `x = 10`"),
+            ParserError::OutsideLoop { span, .. } => Diagnostic::error("E0206", error.to_string()).with_span(*span),
+            ParserError::UnknownLabel { label, span } => Diagnostic::error("E0207", error.to_string())
+                .with_span(*span)
+                .with_help(format!("label a loop with `{}: loop {{ ... }}` or `{}: while ... {{ ... }}`", label, label)),
+            ParserError::BreakValueInWhile { span } => Diagnostic::error("E0208", error.to_string())
+                .with_span(*span)
+                .with_help("use `loop { ... }` to get a value out of a loop"),
+            ParserError::ReturnOutsideProc { span, .. } => Diagnostic::error("E0209", error.to_string()).with_span(*span),
+            ParserError::Lexer(e) => Diagnostic::from(e),
+        }
+    }
+}
+
+impl From<&CompileError> for Diagnostic {
+    fn from(error: &CompileError) -> Diagnostic {
+        match error {
+            CompileError::Unsupported { span, .. } => {
+                Diagnostic::error("E0301", error.to_string()).with_span(*span)
+            }
+            CompileError::UnknownProc { span, .. } => Diagnostic::error("E0304", error.to_string())
+                .with_span(*span)
+                .with_help("define it with `proc name(a, b) { ... }`"),
+            CompileError::ArgumentCount { span, .. } => {
+                Diagnostic::error("E0305", error.to_string()).with_span(*span)
+            }
+            CompileError::DuplicateProc { span, .. } => Diagnostic::error("E0306", error.to_string())
+                .with_span(*span)
+                .with_help("give one of them another name"),
+            CompileError::UndefinedVariable { name, span } => Diagnostic::error("E0307", error.to_string())
+                .with_span(*span)
+                .with_help(format!("give it a value first, e.g. `{} := 0`", name)),
+            CompileError::TypeMismatch { expected: Type::UInt, span, .. } => Diagnostic::error("E0308", error.to_string())
+                .with_span(*span)
+                .with_help("unsigned integers only mix with integers, which are read as unsigned"),
+            CompileError::TypeMismatch { found: Type::Float, span, .. } => Diagnostic::error("E0308", error.to_string())
+                .with_span(*span)
+                .with_help("floats work with arithmetic, comparisons and print, the rest takes integers"),
+            CompileError::TypeMismatch { found: Type::Char, span, .. } => Diagnostic::error("E0308", error.to_string())
+                .with_span(*span)
+                .with_help("characters can be printed and compared with other characters"),
+            CompileError::TypeMismatch { found: Type::Str, span, .. } => Diagnostic::error("E0308", error.to_string())
+                .with_span(*span)
+                .with_help("strings can be printed and kept in variables, there are no operators for them yet"),
+            CompileError::TypeMismatch { span, .. } => {
+                Diagnostic::error("E0308", error.to_string()).with_span(*span)
+            }
+            CompileError::ZeroStep { span } => Diagnostic::error("E0309", error.to_string())
+                .with_span(*span)
+                .with_help("a step of 0 never gets to the end of the range, a negative step counts down"),
+            CompileError::Io { .. } => Diagnostic::error("E0302", error.to_string()),
+            CompileError::Toolchain { tool, .. } if tool == "nasm" || tool == "ld" => {
+                Diagnostic::error("E0303", error.to_string())
+                    .with_help(format!("make sure `{}` is installed and on your PATH", tool))
+            }
+            CompileError::Toolchain { .. } => Diagnostic::error("E0303", error.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+pub fn emit(diagnostics: &[Diagnostic], file_name: &str, source: &str, format: ErrorFormat) {
+    for diagnostic in diagnostics {
+        match format {
+            ErrorFormat::Human => eprintln!("{}", diagnostic.render(file_name, source)),
+            ErrorFormat::Json => eprintln!("{}", diagnostic.to_json(file_name)),
+        }
+    }
+}
@@ -1,29 +1,127 @@
 // extern crate thiserror;
+use std::collections::VecDeque;
+use std::fmt;
 use std::io;
 use thiserror::Error;
+use unicode_xid::UnicodeXID;
 
 #[derive(Error, Debug)]
 pub enum LexerError {
     #[error("")]
     FileIo(#[from] io::Error),
 
-    #[error("Was expecting {expected:?}, found {found:?}")]
-    MissingExpectedSymbol { expected: TokenType, found: Token },
-
     #[error("Not a valid number {raw:?}")]
-    NumericInvalid { raw: String },
+    NumericInvalid { raw: String, span: Span },
 
     #[error("Cant't find opening {open:?} symbol for {symbol:?}")]
-    MisbalancedBraces { symbol: char, open: char },
+    MisbalancedBraces { symbol: char, open: char, span: Span },
 
-    #[error("")]
-    UnknownSymbol { symbol: String },
+    #[error("Unknown symbol {symbol:?}")]
+    UnknownSymbol { symbol: String, span: Span },
+
+    #[error("Unknown escape sequence `{escape}`")]
+    InvalidEscape { escape: String, span: Span },
+
+    #[error("A character literal holds exactly one character, found {raw:?}")]
+    InvalidCharLiteral { raw: String, span: Span },
+
+    #[error("Unknown keyword set {name:?}")]
+    UnknownKeywords { name: String, span: Span },
+
+    #[error("Unclosed `{{` in string, the expression in it has to end with `}}`")]
+    UnclosedInterpolation { span: Span },
+
+    #[error("Unmatched `}}` in string")]
+    UnmatchedInterpolationBrace { span: Span },
+
+    #[error("Block comment is never closed")]
+    UnterminatedBlockComment { span: Span },
 
     #[error("There was an unexpected End Of file")]
-    UnexpectedEOF,
+    UnexpectedEOF { span: Span },
+}
+
+/* line and col are 1 based, offset is the byte offset into the source */
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+    pub offset: usize,
 }
 
-pub type Token = TokenType;
+/* end is exclusive */
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Span {
+        Span { start, end }
+    }
+
+    /* smallest span covering both self and other */
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: if other.start.offset < self.start.offset { other.start } else { self.start },
+            end: if other.end.offset > self.end.offset { other.end } else { self.end },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Token {
+    pub token_type: TokenType,
+    pub span: Span,
+
+    /* comments are kept around the token they sit next to, for formatters and doc tools */
+    #[allow(dead_code)]
+    pub leading_trivia: Vec<Trivia>,
+    #[allow(dead_code)]
+    pub trailing_trivia: Vec<Trivia>,
+
+    /* a keyword as it was written, when that's a translation like `यदि` for `if` */
+    pub spelling: Option<String>,
+}
+
+impl Token {
+    pub fn new(token_type: TokenType, span: Span) -> Token {
+        Token { token_type, span, leading_trivia: vec![], trailing_trivia: vec![], spelling: None }
+    }
+}
+
+/* what messages call the token, in the words the program used */
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.spelling {
+            Some(spelling) => write!(f, "keyword `{}`", spelling),
+            None => write!(f, "{}", self.token_type),
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, Clone)]
+pub enum TriviaKind {
+    /* # ... */
+    Line,
+
+    /* ## ... */
+    Doc,
+
+    /* #[ ... ]#, these nest */
+    Block,
+}
+
+/* text is the comment without its # / ## / #[ ]# markers */
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum NumericType {
@@ -32,6 +130,7 @@ pub enum NumericType {
 }
 
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
     EOF,
@@ -43,17 +142,49 @@ pub enum TokenType {
 
     String(String),
 
+    /* a string with `{expr}` in it, split into text and the source of each expression */
+    InterpolatedString(Vec<StringPart>),
+
+    Char(char),
+
     /* actions * + */
     Operators(String),
 
     /* Seq of chars */
     Identifier(String),
 
-    Numeric{raw: String, hint: NumericType},
+    Numeric{raw: String, hint: NumericType, suffix: Option<String>},
 
     Symobl(String),
 
-    Comment
+    /* stands in for a literal the lexer reported an error for, so the parser can carry on */
+    Invalid,
+}
+
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenType::EOF => write!(f, "end of file"),
+            TokenType::EOL => write!(f, "end of line"),
+            TokenType::Puncutation { raw, .. } => write!(f, "`{}`", raw),
+            TokenType::String(s) => write!(f, "string {:?}", s),
+            TokenType::InterpolatedString(_) => write!(f, "interpolated string"),
+            TokenType::Char(c) => write!(f, "character {:?}", c),
+            TokenType::Operators(op) => write!(f, "operator `{}`", op),
+            TokenType::Identifier(i) => write!(f, "identifier `{}`", i),
+            TokenType::Numeric { raw, suffix, .. } => write!(f, "number `{}{}`", raw, suffix.as_deref().unwrap_or("")),
+            TokenType::Symobl(sym) => write!(f, "keyword `{}`", sym),
+            TokenType::Invalid => write!(f, "invalid literal"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum StringPart {
+    Text(String),
+
+    /* lexed and parsed again by the parser, start is where source begins in the file */
+    Expr { source: String, start: Position, keywords: Keywords },
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -65,6 +196,65 @@ pub enum PunctuationKind {
 
 type BalancingDepthType = i32;
 
+const OPERATORS: &[&str] = &[
+    "+", "-", "*", "/", "\\", "%", "=", "|", "&", "<", ">", "!", ":", "^", "~", ".",
+    "+=", "-=", "%=", "==", "!=", "<=", ">=",
+    "&&", "||", "++", "--", "**", "<<", ">>",
+    ":=", "->", "..", "..=",
+];
+
+const ENGLISH_KEYWORDS: &[&str] = &[
+    "false", "true", "proc", "if", "else", "loop", "while", "for", "in", "step",
+    "break", "continue", "return", "print", "input", "_",
+];
+
+/* (nepali, english), the lexer hands out the english keyword so the parser only knows one spelling */
+const NEPALI_KEYWORDS: &[(&str, &str)] = &[
+    ("असत्य", "false"),
+    ("सत्य", "true"),
+    ("कार्य", "proc"),
+    ("यदि", "if"),
+    ("नत्र", "else"),
+    ("दोहोर्याउ", "loop"),
+    ("जबसम्म", "while"),
+    ("हरेक", "for"),
+    ("भित्र", "in"),
+    ("पाइला", "step"),
+    ("रोक", "break"),
+    ("जारी", "continue"),
+    ("फर्काउ", "return"),
+    ("छाप", "print"),
+    ("इनपुट", "input"),
+];
+
+/*
+    Which keywords are recognised, english ones always are. Picked with --keywords or,
+    for a single file, a `#!keywords nepali` comment which applies from there on.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keywords {
+    English,
+    Nepali,
+}
+
+impl Keywords {
+    pub fn from_name(name: &str) -> Option<Keywords> {
+        match name {
+            "english" => Some(Keywords::English),
+            "nepali" => Some(Keywords::Nepali),
+            _ => None,
+        }
+    }
+}
+
+/* devanagari digits ०-९ are read as 0-9 */
+fn normalize_digit(c: char) -> char {
+    match c {
+        '०'..='९' => char::from_digit(c as u32 - '०' as u32, 10).unwrap_or(c),
+        _ => c,
+    }
+}
+
 pub struct Lexer<'a> {
     /* human readable */
     pub cur_line: usize,
@@ -73,8 +263,24 @@ pub struct Lexer<'a> {
     /* (raw) code points */
     pub codepoint_offset: usize,
 
+    /* utf-8 bytes */
+    pub byte_offset: usize,
+
+    /* where the token being lexed started */
+    token_start: Position,
+
+    /* EOF was handed out by the iterator */
+    finished: bool,
+
+    source: &'a str,
+
+    /* byte_offset of the first byte of source, not 0 when it was cut out of a bigger file */
+    source_offset: usize,
+
     chars: std::iter::Peekable<std::str::Chars<'a>>,
     balancing_state: std::collections::HashMap<char, BalancingDepthType>,
+
+    keywords: Keywords,
 }
 
 impl<'a> Lexer<'a> {
@@ -84,13 +290,36 @@ impl<'a> Lexer<'a> {
             cur_line: 1,
 
             codepoint_offset: 0,
+            byte_offset: 0,
+
+            token_start: Position { line: 1, col: 1, offset: 0 },
+            finished: false,
 
+            source: chars,
+            source_offset: 0,
             chars: chars.chars().peekable(),
             balancing_state: std::collections::HashMap::new(),
+
+            keywords: Keywords::English,
         }
     }
 
-    fn map_balance(c: &char) -> char {
+    pub fn with_keywords(mut self, keywords: Keywords) -> Lexer<'a> {
+        self.keywords = keywords;
+        self
+    }
+
+    /* for source cut out of a bigger file, so spans still point into that file */
+    pub fn with_start(mut self, start: Position) -> Lexer<'a> {
+        self.cur_line = start.line;
+        self.cur_col = start.col;
+        self.byte_offset = start.offset;
+        self.source_offset = start.offset;
+        self.token_start = start;
+        self
+    }
+
+    pub fn map_balance(c: &char) -> char {
         match c {
             '{' => '}',
             ')' => '(',
@@ -102,8 +331,21 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    pub fn current_position(&self) -> Position {
+        Position {
+            line: self.cur_line,
+            col: self.cur_col,
+            offset: self.byte_offset,
+        }
+    }
+
+    /* span from the start of the current token up to the last consumed char */
+    fn token_span(&self) -> Span {
+        Span::new(self.token_start, self.current_position())
+    }
+
     fn push_symbol(&mut self, c: &char) -> BalancingDepthType {
-        if let Some(v) = self.balancing_state.get_mut(&c) {
+        if let Some(v) = self.balancing_state.get_mut(c) {
             *v += 1;
             *v - 1
         } else {
@@ -113,99 +355,353 @@ impl<'a> Lexer<'a> {
     }
 
     fn pop_symbol(&mut self, c: &char) -> Result<BalancingDepthType, LexerError> {
-        if let Some(v) = self.balancing_state.get_mut(&Lexer::map_balance(&c)) {
+        if let Some(v) = self.balancing_state.get_mut(&Lexer::map_balance(c)) {
             if *v >= 1 {
                 *v -= 1;
-                Ok(*v)
-            } else {
-                Err(LexerError::MisbalancedBraces {
-                    symbol: *c,
-                    open: Lexer::map_balance(&c)
-                })
+                return Ok(*v);
             }
-        } else {
-            Err(LexerError::MisbalancedBraces {
-                symbol: *c,
-                open: Lexer::map_balance(&c)
-            })
         }
+
+        Err(LexerError::MisbalancedBraces {
+            symbol: *c,
+            open: Lexer::map_balance(c),
+            span: self.token_span(),
+        })
     }
 
+    /*
+        Numbers are decimal, or hex / binary / octal with a 0x / 0b / 0o prefix. Decimals
+        may have a fraction and an exponent, any of them can end with a type suffix (10i64).
+        raw keeps the prefix but not the `_` separators or the suffix.
+    */
     fn match_number(&mut self, start: char) ->  Result<TokenType, LexerError> {
-        let mut seen_dot = false;
-        let radix = 10;
+        let mut hint = if start == '.' { NumericType::FloatingPoint } else { NumericType::Integer };
+        let mut seen_exponent = false;
 
+        let start = normalize_digit(start);
         let mut num = start.to_string();
 
-        if start == '.' {
-            seen_dot = true;
+        let radix = match (start, self.peek_char(0)) {
+            ('0', Some('x')) => 16,
+            ('0', Some('b')) => 2,
+            ('0', Some('o')) => 8,
+            _ => 10,
+        };
+        if radix != 10 {
+            num.extend(self.consume_char());
         }
 
-
         loop{
-            match self.chars.peek() {
-                Some(c) if *c == '.' && !seen_dot => {
-                    num.push(*c);
+            match self.peek_char(0) {
+                Some('_') => {
                     self.consume_char();
-                    seen_dot = true
                 }
-                Some(c) if c.is_digit(radix) => {
-                    num.push(*c);
+                Some(c) if normalize_digit(c).is_digit(radix) => {
+                    num.push(normalize_digit(c));
                     self.consume_char();
                 }
-                Some(c) if *c == '_' => {
+                /* `0..10` is a range and not the number `0.` */
+                Some('.') if radix == 10 && hint == NumericType::Integer && self.peek_char(1) != Some('.') => {
+                    num.push('.');
                     self.consume_char();
+                    hint = NumericType::FloatingPoint;
                 }
-                Some(c) if c.is_ascii_alphabetic() || c.is_digit(10) => {
-                    num.push(*c);
+                Some(e @ ('e' | 'E')) if radix == 10 && !seen_exponent && self.is_exponent_ahead() => {
+                    num.push(e);
                     self.consume_char();
-                    return Err(LexerError::NumericInvalid { raw: num.to_string() });
-                }
-                _ => {
-                    break Ok(TokenType::Numeric {raw: num, hint: if seen_dot {NumericType::FloatingPoint} else {NumericType::Integer}});
+                    if let Some(sign @ ('+' | '-')) = self.peek_char(0) {
+                        num.push(sign);
+                        self.consume_char();
+                    }
+                    seen_exponent = true;
+                    hint = NumericType::FloatingPoint;
                 }
+                _ => break,
             }
         }
 
+        let mut suffix = String::new();
+        while let Some(c) = self.peek_char(0).filter(|c| c.is_ascii_alphanumeric() || *c == '_') {
+            suffix.push(c);
+            self.consume_char();
+        }
+
+        /* a prefix without any digits, like `0x` */
+        if radix != 10 && num.len() == 2 {
+            return Err(LexerError::NumericInvalid { raw: format!("{}{}", num, suffix), span: self.token_span() });
+        }
+
+        let suffix = match suffix.as_str() {
+            "" => None,
+            "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "u64" if hint == NumericType::Integer => Some(suffix.clone()),
+            "f32" | "f64" if radix == 10 => {
+                hint = NumericType::FloatingPoint;
+                Some(suffix.clone())
+            }
+            _ => {
+                return Err(LexerError::NumericInvalid { raw: format!("{}{}", num, suffix), span: self.token_span() });
+            }
+        };
+
+        Ok(TokenType::Numeric { raw: num, hint, suffix })
+    }
+
+    /* after an `e`, only digits (optionally signed) make it an exponent */
+    fn is_exponent_ahead(&self) -> bool {
+        match self.peek_char(1) {
+            Some('+' | '-') => matches!(self.peek_char(2), Some(c) if normalize_digit(c).is_ascii_digit()),
+            Some(c) => normalize_digit(c).is_ascii_digit(),
+            None => false,
+        }
     }
 
     fn match_string(&mut self, start: char)-> Result<TokenType, LexerError> {
+        /* """ opens a multiline string, a newline right after it isn't part of the string */
+        if self.peek_char(0) == Some(start) && self.peek_char(1) == Some(start) {
+            self.consume_char();
+            self.consume_char();
+            if self.peek_char(0) == Some('\n') {
+                self.consume_char();
+            }
+            return self.match_quoted(&start.to_string().repeat(3), true).map(Lexer::string_token);
+        }
+
+        self.match_quoted(&start.to_string(), true).map(Lexer::string_token)
+    }
+
+    /* strings without any `{expr}` stay plain strings */
+    fn string_token(mut parts: Vec<StringPart>) -> TokenType {
+        match parts.as_slice() {
+            [] => TokenType::String(String::new()),
+            [StringPart::Text(_)] => match parts.pop() {
+                Some(StringPart::Text(text)) => TokenType::String(text),
+                _ => unreachable!(),
+            },
+            _ => TokenType::InterpolatedString(parts),
+        }
+    }
+
+    fn match_char(&mut self, start: char) -> Result<TokenType, LexerError> {
+        let raw = match self.match_quoted(&start.to_string(), false)?.pop() {
+            Some(StringPart::Text(text)) => text,
+            _ => String::new(),
+        };
+        let mut chars = raw.chars();
+
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(TokenType::Char(c)),
+            _ => Err(LexerError::InvalidCharLiteral { raw, span: self.token_span() }),
+        }
+    }
+
+    /*
+        Everything up to the closing quote, with escapes resolved. With interpolate, `{expr}`
+        is cut out as its own part and `{{` / `}}` stand for the braces themselves.
+    */
+    fn match_quoted(&mut self, quote: &str, interpolate: bool) -> Result<Vec<StringPart>, LexerError> {
+        let mut parts = vec![];
         let mut return_string = String::new();
 
+        /* a bad escape doesn't stop the string, so lexing picks up after the closing quote */
+        let mut first_error = None;
+
         loop {
-            match self.chars.next() {
-                Some(c) if c == start  => {
-                    break Ok(TokenType::String(return_string));
+            if self.peek_str(quote) {
+                for _ in quote.chars() {
+                    self.consume_char();
+                }
+                if !return_string.is_empty() || parts.is_empty() {
+                    parts.push(StringPart::Text(return_string));
+                }
+                break match first_error {
+                    Some(e) => Err(e),
+                    None => Ok(parts),
+                };
+            }
+
+            let escape_start = self.current_position();
+
+            match self.consume_char() {
+                Some(brace @ ('{' | '}')) if interpolate && self.peek_char(0) == Some(brace) => {
+                    self.consume_char();
+                    return_string.push(brace);
                 }
-                Some(c) if c == '\\'  => {
-                    match self.chars.peek(){
-                        Some(s) if *s == '"' =>{
-                            return_string.push(*s);
-                            self.consume_char();
-                        }
-                        _=>{}
+
+                Some('{') if interpolate => {
+                    let source = self.match_interpolation(escape_start)?;
+                    if !return_string.is_empty() {
+                        parts.push(StringPart::Text(std::mem::take(&mut return_string)));
                     }
+                    parts.push(StringPart::Expr {
+                        source,
+                        start: Position { col: escape_start.col + 1, offset: escape_start.offset + 1, ..escape_start },
+                        keywords: self.keywords,
+                    });
+                }
 
+                Some('}') if interpolate => {
+                    first_error.get_or_insert(LexerError::UnmatchedInterpolationBrace {
+                        span: Span::new(escape_start, self.current_position()),
+                    });
                 }
-            
+
+                Some('\\') => match self.match_escape(escape_start) {
+                    Ok(Some(c)) => return_string.push(c),
+                    Ok(None) => {}
+                    Err(e) => {
+                        first_error.get_or_insert(e);
+                    }
+                },
+
                 Some(c) => {
                     return_string.push(c);
                 }
 
                 None => {
-                    break Err(LexerError::UnknownSymbol { symbol: "Unexpected EOF".to_string() })
+                    break Err(LexerError::UnexpectedEOF { span: self.token_span() })
                 }
             }
         }
 
     }
 
+    /*
+        called after the `{`, the source up to its matching `}`, skipping over braces in nested
+        strings. An unclosed one stops before the newline, so the line still ends.
+    */
+    fn match_interpolation(&mut self, open: Position) -> Result<String, LexerError> {
+        let mut source = String::new();
+        let mut depth = 0;
+        let mut quote = None;
+
+        loop {
+            let c = match self.peek_char(0) {
+                Some(c) if c != '\n' => {
+                    self.consume_char();
+                    c
+                }
+                _ => return Err(LexerError::UnclosedInterpolation { span: Span::new(open, self.current_position()) }),
+            };
+
+            match (quote, c) {
+                (None, '}') if depth == 0 => return Ok(source),
+                (None, '}') => depth -= 1,
+                (None, '{') => depth += 1,
+                (None, '"' | '\'') => quote = Some(c),
+                (Some(_), '\\') => {
+                    source.push(c);
+                    source.extend(self.consume_char());
+                    continue;
+                }
+                (Some(q), c) if q == c => quote = None,
+                _ => {}
+            }
+
+            source.push(c);
+        }
+    }
+
+    /* called after the `\`, a line continuation resolves to None */
+    fn match_escape(&mut self, escape_start: Position) -> Result<Option<char>, LexerError> {
+        let escaped = match self.consume_char() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('\'') => '\'',
+            Some('"') => '"',
+            Some('\n') => {
+                while matches!(self.peek_char(0), Some(c) if c.is_whitespace()) {
+                    self.consume_char();
+                }
+                return Ok(None);
+            }
+            Some('x') => {
+                let mut digits = String::new();
+                while digits.len() < 2 && matches!(self.peek_char(0), Some(c) if c.is_ascii_hexdigit()) {
+                    digits.push(self.consume_char().unwrap_or_default());
+                }
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(value) if digits.len() == 2 && value <= 0x7f => value as char,
+                    _ => return Err(self.invalid_escape(escape_start)),
+                }
+            }
+            Some('u') => {
+                if self.peek_char(0) != Some('{') {
+                    return Err(self.invalid_escape(escape_start));
+                }
+                self.consume_char();
+
+                let mut digits = String::new();
+                while matches!(self.peek_char(0), Some(c) if c.is_ascii_hexdigit()) {
+                    digits.push(self.consume_char().unwrap_or_default());
+                }
+                if self.peek_char(0) != Some('}') {
+                    return Err(self.invalid_escape(escape_start));
+                }
+                self.consume_char();
+
+                match u32::from_str_radix(&digits, 16).ok().filter(|_| digits.len() <= 6).and_then(char::from_u32) {
+                    Some(c) => c,
+                    None => return Err(self.invalid_escape(escape_start)),
+                }
+            }
+            _ => return Err(self.invalid_escape(escape_start)),
+        };
+
+        Ok(Some(escaped))
+    }
+
+    fn invalid_escape(&self, escape_start: Position) -> LexerError {
+        let span = Span::new(escape_start, self.current_position());
+        LexerError::InvalidEscape {
+            escape: self.source[escape_start.offset - self.source_offset..span.end.offset - self.source_offset].to_string(),
+            span,
+        }
+    }
+
+    /* r"..." or r#"..."# with any number of #, nothing inside is escaped */
+    fn is_raw_string_start(&self) -> bool {
+        let mut n = 0;
+        while self.peek_char(n) == Some('#') {
+            n += 1;
+        }
+        self.peek_char(n) == Some('"')
+    }
+
+    fn match_raw_string(&mut self) -> Result<TokenType, LexerError> {
+        let mut hashes = 0;
+        while self.peek_char(0) == Some('#') {
+            self.consume_char();
+            hashes += 1;
+        }
+        self.consume_char();
+
+        let closing = format!("\"{}", "#".repeat(hashes));
+        let mut return_string = String::new();
+
+        loop {
+            if self.peek_str(&closing) {
+                for _ in closing.chars() {
+                    self.consume_char();
+                }
+                break Ok(TokenType::String(return_string));
+            }
+
+            match self.consume_char() {
+                Some(c) => return_string.push(c),
+                None => break Err(LexerError::UnexpectedEOF { span: self.token_span() }),
+            }
+        }
+    }
+
     fn match_identifier(&mut self, start: char) -> Result<TokenType, LexerError> {
         let mut return_string = start.to_string();
 
         loop {
             match self.chars.peek() {
-                Some(c) if c.is_ascii_alphabetic() || c.is_digit(10) ||*c == '_'  => {
+                Some(c) if c.is_xid_continue() => {
                     return_string.push(*c);
                     self.consume_char();
                 }
@@ -218,47 +714,35 @@ impl<'a> Lexer<'a> {
     }
 
     fn match_symbol(&mut self, identifier: String) -> TokenType {
-        if match identifier.as_ref() {
-            "false" | "true" | "proc" | "if" | "else" | "loop" | "break" | "print" | "input" => true,
-            _ => false
-        }{
-            TokenType::Symobl(identifier)
-        }else {
-            TokenType::Identifier(identifier)
+        if ENGLISH_KEYWORDS.contains(&identifier.as_str()) {
+            return TokenType::Symobl(identifier);
+        }
+
+        if self.keywords == Keywords::Nepali {
+            if let Some((_, english)) = NEPALI_KEYWORDS.iter().find(|(nepali, _)| *nepali == identifier) {
+                return TokenType::Symobl(english.to_string());
+            }
         }
+
+        TokenType::Identifier(identifier)
     }
 
     fn match_operator(&mut self, start: char) -> Result<TokenType, LexerError> {
         let mut return_operators = start.to_string();
 
-        match self.chars.peek() {
-            Some(c) if *c == '=' && (start == '+' || start == '-' || start == '=' || start == '>' || start == '<' || start == '%')   => {
-                return_operators.push(*c);
-                self.consume_char();
-            }
-            Some(c) if *c == '|' && (start == '|')   => {
-                return_operators.push(*c);
-                self.consume_char();
-            }
-            Some(c) if *c == '&' && (start == '&')   => {
-                return_operators.push(*c);
-                self.consume_char();
-            }
-            Some(c) if *c == '+' && start == '+' => {
-                return_operators.push(*c);
-                self.consume_char();
-            }
-            Some(c) if *c == '-' && start == '-' => {
-                return_operators.push(*c);
-                self.consume_char();
-            }
-            None => return Err(LexerError::UnexpectedEOF),
-            _ => {
-                return Ok(TokenType::Operators(return_operators));
-            }
-
+        /* longest match, every operator is at most three chars long */
+        let extra = (1..=2)
+            .rev()
+            .find(|&n| {
+                let candidate: String = std::iter::once(start).chain(self.chars.clone().take(n)).collect();
+                candidate.chars().count() == n + 1 && OPERATORS.contains(&candidate.as_str())
+            })
+            .unwrap_or(0);
+        for _ in 0..extra {
+            return_operators.extend(self.consume_char());
         }
-        return Ok(TokenType::Operators(return_operators));
+
+        Ok(TokenType::Operators(return_operators))
 
     }
 
@@ -273,40 +757,47 @@ impl<'a> Lexer<'a> {
                 raw: c,
                 kind: PunctuationKind::Close(self.pop_symbol(&c)?),
             }),
-            '0' ..= '9' | '.'=> self.match_number(c),
-            '"' | '\'' => self.match_string(c),
-            '+' | '-' | '*' | '/' | '\\' | '%' |'=' | '|' | '&' | '<' | '>' => self.match_operator(c),
+            '0' ..= '9' | '०' ..= '९' => self.match_number(c),
+            '.' if matches!(self.peek_char(0), Some(c) if normalize_digit(c).is_ascii_digit()) => self.match_number(c),
+            '"' => self.match_string(c),
+            '\'' => self.match_char(c),
+            '+' | '-' | '*' | '/' | '\\' | '%' |'=' | '|' | '&' | '<' | '>' | '!' | ':' | '^' | '~' | '.' => self.match_operator(c),
             ','| ';' => Ok(TokenType::Puncutation {
                 raw: c,
                 kind: PunctuationKind::Seperator
             }),
-            '#' => {
-                while let Some(c) = self.chars.next() {
-                    if c == '\n' {
-                        break;
-                    }
-                }
-                Ok(TokenType::Comment)
-            },
             '\n' => Ok(TokenType::EOL),
-            'a' ..= 'z' | 'A' ..= 'Z'=> self.match_identifier(c), 
+            'r' if self.is_raw_string_start() => self.match_raw_string(),
+            c if c == '_' || c.is_xid_start() => self.match_identifier(c),
             _ => Err(LexerError::UnknownSymbol {
                 symbol: c.to_string(),
+                span: self.token_span(),
             }),
         }
     }
 
+    /* looks n chars ahead without consuming anything */
+    fn peek_char(&self, n: usize) -> Option<char> {
+        self.chars.clone().nth(n)
+    }
+
+    fn peek_str(&self, expected: &str) -> bool {
+        let mut ahead = self.chars.clone();
+        expected.chars().all(|c| ahead.next() == Some(c))
+    }
+
     pub fn consume_char(&mut self) -> Option<char> {
         match self.chars.next() {
             Some(c) => {
-                self.cur_col += 1;
-
                 if c == '\n' {
                     self.cur_line += 1;
+                    self.cur_col = 1;
+                } else {
                     self.cur_col += 1;
                 }
 
                 self.codepoint_offset += 1;
+                self.byte_offset += c.len_utf8();
                 Some(c)
             }
             None => None,
@@ -322,31 +813,290 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    pub fn next_token(&mut self) -> Result<TokenType, LexerError> {
-        self.skip_whitespace();
-        
-        if let Some(c) = self.consume_char() {
-            self.transform_to_type(c)
-        } else {
-            Ok(TokenType::EOF)
+    /* called on the `#`, comments that aren't doc or block comments run up to the newline */
+    fn match_comment(&mut self) -> Result<Trivia, LexerError> {
+        let start = self.current_position();
+        self.consume_char();
+
+        let (kind, text) = match self.peek_char(0) {
+            Some('[') => {
+                self.consume_char();
+                (TriviaKind::Block, self.match_block_comment(start)?)
+            }
+            Some('#') => {
+                self.consume_char();
+                (TriviaKind::Doc, self.match_line_comment())
+            }
+            _ => (TriviaKind::Line, self.match_line_comment()),
+        };
+
+        let span = Span::new(start, self.current_position());
+        if kind == TriviaKind::Line {
+            self.apply_directive(&text, span)?;
         }
+
+        Ok(Trivia { kind, text, span })
     }
 
-    pub fn peek_next_token(&mut self) -> Result<TokenType, LexerError>{
+    /* `#!keywords nepali` switches the keyword table for the rest of the file */
+    fn apply_directive(&mut self, comment: &str, span: Span) -> Result<(), LexerError> {
+        if let Some(name) = comment.strip_prefix("!keywords") {
+            let name = name.trim();
+            self.keywords = Keywords::from_name(name)
+                .ok_or_else(|| LexerError::UnknownKeywords { name: name.to_string(), span })?;
+        }
+        Ok(())
+    }
+
+    fn match_line_comment(&mut self) -> String {
+        let mut text = String::new();
+        while let Some(c) = self.peek_char(0).filter(|c| *c != '\n') {
+            text.push(c);
+            self.consume_char();
+        }
+        text
+    }
+
+    fn match_block_comment(&mut self, start: Position) -> Result<String, LexerError> {
+        let mut text = String::new();
+        let mut depth = 1;
+
+        loop {
+            if self.peek_str("#[") {
+                depth += 1;
+            } else if self.peek_str("]#") {
+                depth -= 1;
+                if depth == 0 {
+                    self.consume_char();
+                    self.consume_char();
+                    break Ok(text);
+                }
+            }
+
+            match self.consume_char() {
+                Some(c) => text.push(c),
+                None => {
+                    break Err(LexerError::UnterminatedBlockComment {
+                        span: Span::new(start, self.current_position()),
+                    })
+                }
+            }
+        }
+    }
 
-        let cur_col = self.cur_col.clone();
-        let cur_line = self.cur_line.clone();
-        let codepoint_offset = self.codepoint_offset.clone();
+    pub fn next_token(&mut self) -> Result<Token, LexerError> {
+        /*
+            comments on their own lines take their newline with them, block ones too, so they
+            don't end a statement. After code the newline is left, and ends it as usual
+        */
+        let mut leading_trivia = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.peek_char(0) != Some('#') {
+                break;
+            }
+            let trivia = self.match_comment()?;
+            if self.peek_char(0) == Some('\n') {
+                self.consume_char();
+            }
+            leading_trivia.push(trivia);
+        }
 
-        if let Some(c) = self.consume_char() { 
-            let t_to_type = self.transform_to_type(c);
-            self.cur_col = cur_col;
-            self.cur_line = cur_line;
-            self.codepoint_offset = codepoint_offset;
+        self.token_start = self.current_position();
 
-            t_to_type
+        let mut token = if let Some(c) = self.consume_char() {
+            let token_type = self.transform_to_type(c)?;
+            let span = self.token_span();
+            let mut token = Token::new(token_type, span);
+            if let TokenType::Symobl(sym) = &token.token_type {
+                let written = &self.source[span.start.offset - self.source_offset..span.end.offset - self.source_offset];
+                if written != sym {
+                    token.spelling = Some(written.to_string());
+                }
+            }
+            token
         } else {
-            Ok(TokenType::EOF)
+            Token::new(TokenType::EOF, self.token_span())
+        };
+        token.leading_trivia = leading_trivia;
+
+        /* a comment later on the same line belongs to this token, the newline is left for EOL */
+        if token.token_type != TokenType::EOL {
+            self.skip_whitespace();
+            while self.peek_char(0) == Some('#') {
+                token.trailing_trivia.push(self.match_comment()?);
+                self.skip_whitespace();
+            }
+        }
+
+        Ok(token)
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token, LexerError>;
+
+    /* yields every token up to and including EOF */
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let token = self.next_token();
+        if let Ok(Token { token_type: TokenType::EOF, .. }) = token {
+            self.finished = true;
+        }
+        Some(token)
+    }
+}
+
+/* buffers tokens from a Lexer so any number of them can be looked at ahead of time */
+pub struct TokenStream<'a> {
+    lexer: Lexer<'a>,
+    buffer: VecDeque<Result<Token, LexerError>>,
+    eof: Option<Token>,
+}
+
+impl<'a> TokenStream<'a> {
+    pub fn new(lexer: Lexer<'a>) -> TokenStream<'a> {
+        TokenStream {
+            lexer,
+            buffer: VecDeque::new(),
+            eof: None,
+        }
+    }
+
+    pub fn current_position(&self) -> Position {
+        self.lexer.current_position()
+    }
+
+    /* lexer errors come out in order with the tokens, once the input is done EOF repeats */
+    pub fn next_token(&mut self) -> Result<Token, LexerError> {
+        match self.buffer.pop_front().or_else(|| self.lexer.next()) {
+            Some(Ok(token)) => {
+                if token.token_type == TokenType::EOF {
+                    self.eof = Some(token.clone());
+                }
+                Ok(token)
+            }
+            Some(Err(e)) => Err(e),
+            None => Ok(self.eof.clone().expect("the lexer ends with EOF")),
+        }
+    }
+
+    /* the k-th token not yet taken by next_token (0 being the very next), lexer errors are skipped */
+    pub fn peek_nth(&mut self, k: usize) -> &Token {
+        while self.buffer.iter().filter(|t| t.is_ok()).count() <= k {
+            match self.lexer.next() {
+                Some(token) => self.buffer.push_back(token),
+                None => break,
+            }
+        }
+
+        let mut ahead = self.buffer.iter().filter_map(|t| t.as_ref().ok());
+        match ahead.nth(k) {
+            Some(token) => token,
+            None => self
+                .buffer
+                .iter()
+                .rev()
+                .find_map(|t| t.as_ref().ok())
+                .or(self.eof.as_ref())
+                .expect("the lexer ends with EOF"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* the next count tokens, errors by their debug output */
+    fn types(tokens: &mut TokenStream, count: usize) -> Vec<Result<TokenType, String>> {
+        (0..count)
+            .map(|_| tokens.next_token().map(|t| t.token_type).map_err(|e| format!("{:?}", e)))
+            .collect()
+    }
+
+    #[test]
+    fn peeking_does_not_consume() {
+        let mut tokens = TokenStream::new(Lexer::new("a b"));
+        assert_eq!(tokens.peek_nth(1).token_type, TokenType::Identifier("b".to_string()));
+        assert_eq!(tokens.peek_nth(0).token_type, TokenType::Identifier("a".to_string()));
+
+        assert_eq!(
+            types(&mut tokens, 2),
+            [Ok(TokenType::Identifier("a".to_string())), Ok(TokenType::Identifier("b".to_string()))]
+        );
+    }
+
+    #[test]
+    fn peeking_skips_errors_but_keeps_them_in_order() {
+        let mut tokens = TokenStream::new(Lexer::new("a 0x b"));
+        assert_eq!(tokens.peek_nth(1).token_type, TokenType::Identifier("b".to_string()));
+
+        assert_eq!(tokens.next_token().map(|t| t.token_type).ok(), Some(TokenType::Identifier("a".to_string())));
+        assert!(matches!(tokens.next_token(), Err(LexerError::NumericInvalid { .. })));
+        assert_eq!(tokens.next_token().map(|t| t.token_type).ok(), Some(TokenType::Identifier("b".to_string())));
+    }
+
+    #[test]
+    fn eof_repeats() {
+        let mut tokens = TokenStream::new(Lexer::new("a"));
+        assert_eq!(tokens.peek_nth(5).token_type, TokenType::EOF);
+        assert_eq!(
+            types(&mut tokens, 4),
+            [Ok(TokenType::Identifier("a".to_string())), Ok(TokenType::EOF), Ok(TokenType::EOF), Ok(TokenType::EOF)]
+        );
+        assert_eq!(tokens.peek_nth(0).token_type, TokenType::EOF);
+        assert_eq!(tokens.peek_nth(3).token_type, TokenType::EOF);
+    }
+
+    /* every token up to EOF, with the text of the comments before and after it */
+    fn trivia(source: &str) -> Vec<(TokenType, Vec<String>, Vec<String>)> {
+        let texts = |trivia: &[Trivia]| trivia.iter().map(|t| t.text.clone()).collect::<Vec<_>>();
+        Lexer::new(source)
+            .map(|token| token.expect("source lexes"))
+            .map(|token| (token.token_type.clone(), texts(&token.leading_trivia), texts(&token.trailing_trivia)))
+            .collect()
+    }
+
+    #[test]
+    fn comments_are_kept_as_trivia() {
+        let tokens = trivia("## doc\n#[ a #[ b ]# c ]# x # after\ny");
+        let x = TokenType::Identifier("x".to_string());
+        let y = TokenType::Identifier("y".to_string());
+
+        assert_eq!(tokens[0], (x, vec![" doc".to_string(), " a #[ b ]# c ".to_string()], vec![" after".to_string()]));
+        assert_eq!(tokens[1].0, TokenType::EOL);
+        assert_eq!(tokens[2], (y, vec![], vec![]));
+    }
+
+    #[test]
+    fn comment_kinds() {
+        let mut lexer = Lexer::new("# line\n## doc\n#[ block ]# x");
+        let token = lexer.next_token().expect("source lexes");
+        let kinds: Vec<TriviaKind> = token.leading_trivia.iter().map(|t| t.kind.clone()).collect();
+        assert_eq!(kinds, [TriviaKind::Line, TriviaKind::Doc, TriviaKind::Block]);
+    }
+
+    #[test]
+    fn only_comments_after_code_end_the_statement() {
+        let types = |source: &str| trivia(source).into_iter().map(|t| t.0).collect::<Vec<_>>();
+        let x = TokenType::Identifier("x".to_string());
+        let y = TokenType::Identifier("y".to_string());
+
+        assert_eq!(types("x\n# a\n#[ b ]#\ny"), [x.clone(), TokenType::EOL, y.clone(), TokenType::EOF]);
+        assert_eq!(types("x # a\ny"), [x.clone(), TokenType::EOL, y.clone(), TokenType::EOF]);
+        assert_eq!(types("x #[ a ]#\ny"), [x, TokenType::EOL, y, TokenType::EOF]);
+    }
+
+    #[test]
+    fn unclosed_nested_block_comment() {
+        /* the inner `]#` only closes the inner comment */
+        let mut lexer = Lexer::new("x\n#[ a #[ b ]# c\ny");
+        assert_eq!(lexer.next_token().map(|t| t.token_type).ok(), Some(TokenType::Identifier("x".to_string())));
+        assert_eq!(lexer.next_token().map(|t| t.token_type).ok(), Some(TokenType::EOL));
+        assert!(matches!(lexer.next_token(), Err(LexerError::UnterminatedBlockComment { .. })));
+    }
+}
@@ -1,11 +1,13 @@
 use clap::{arg, Command};
 
-use lexer::TokenType;
+use diagnostics::{Diagnostic, ErrorFormat};
+use lexer::Keywords;
 
 mod lexer;
 mod ast;
 mod parser;
 mod compiler;
+mod diagnostics;
 
 
 fn cli() -> Command {
@@ -18,34 +20,75 @@ fn cli() -> Command {
             Command::new("file")
                 .about("Use a df file")
                 .arg(arg!(<REMOTE> "The remote to open a file"))
+                .arg(
+                    arg!(--"error-format" <FORMAT> "How errors are reported")
+                        .value_parser(["human", "json"])
+                        .default_value("human"),
+                )
+                .arg(
+                    arg!(--keywords <SET> "Which keywords to accept, on top of the english ones")
+                        .value_parser(["english", "nepali"])
+                        .default_value("english"),
+                )
                 .arg_required_else_help(true),
         )
 }
 
-fn main() -> std::io::Result<()> {
+/* lex, parse and compile the source, everything that went wrong is returned as diagnostics */
+fn compile(file_name: &str, text: &str, keywords: Keywords) -> Result<(), Vec<Diagnostic>> {
+    let lex  = lexer::Lexer::new(text).with_keywords(keywords);
 
-    let matches = cli().get_matches();
+    let mut par = parser::Parser::new(lex);
+    let par_program = par
+        .walk()
+        .map_err(|errors| errors.iter().map(Diagnostic::from).collect::<Vec<_>>())?;
+
+    // println!("{:#?}", par_program);
+    let mut new_compiler = compiler::Compile::new("output", file_name);
+
+    let mut errors = new_compiler.declare_procs(&par_program.stmts);
+    errors.extend(
+        par_program
+            .stmts
+            .into_iter()
+            .filter_map(|stmt| new_compiler.compile_statement(stmt).err()),
+    );
+    let errors: Vec<Diagnostic> = errors.iter().map(Diagnostic::from).collect();
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
 
-    match matches.subcommand() {
-        Some(("file", sub_matches)) => {
-            let file_name = sub_matches.get_one::<String>("REMOTE").expect("required");
-            let text = std::fs::read_to_string(file_name)?;
-            let mut lex  = lexer::Lexer::new(&text);
+    new_compiler.execute().map_err(|e| vec![Diagnostic::from(&e)])
+}
+
+fn main() {
 
-            
-            let mut par = parser::Parser::new(lex);
-            let par_program =  par.walk();
+    let matches = cli().get_matches();
 
-            // println!("{:#?}", par_program);
-            println!("{:?}", par_program);
-            let mut new_compiler = compiler::Compile::new("output");
+    if let Some(("file", sub_matches)) = matches.subcommand() {
+        let file_name = sub_matches.get_one::<String>("REMOTE").expect("required");
+        let error_format = match sub_matches.get_one::<String>("error-format").map(String::as_str) {
+            Some("json") => ErrorFormat::Json,
+            _ => ErrorFormat::Human,
+        };
+        let keywords = sub_matches
+            .get_one::<String>("keywords")
+            .and_then(|name| Keywords::from_name(name))
+            .unwrap_or(Keywords::English);
 
-            for expr in par_program.exprs {
-                new_compiler.evaluate(expr)
+        let text = match std::fs::read_to_string(file_name) {
+            Ok(text) => text,
+            Err(e) => {
+                let diagnostic = Diagnostic::error("E0001", format!("Couldn't read {:?}, {}", file_name, e));
+                diagnostics::emit(&[diagnostic], file_name, "", error_format);
+                std::process::exit(1);
             }
-            new_compiler.execute();
+        };
+
+        if let Err(errors) = compile(file_name, &text, keywords) {
+            diagnostics::emit(&errors, file_name, &text, error_format);
+            std::process::exit(1);
         }
-        _ => {}
     }
-    Ok(())
-}
\ No newline at end of file
+}
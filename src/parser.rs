@@ -3,304 +3,860 @@ use crate::lexer::*;
 
 use thiserror::Error;
 
+/* every binary operator, from the loosest to the tightest binding. They're all left associative */
+const BINARY_OPERATORS: &[(&str, u8, Operator)] = &[
+    ("||", 2, Operator::BooleanOr),
+
+    ("&&", 3, Operator::BooleanAnd),
+
+    ("==", 4, Operator::Equal),
+    ("!=", 4, Operator::NotEqual),
+
+    ("<", 5, Operator::LessThan),
+    (">", 5, Operator::GreaterThan),
+    ("<=", 5, Operator::LessThanOrEqual),
+    (">=", 5, Operator::GreaterThantOrEqual),
+
+    ("+", 6, Operator::Plus),
+    ("-", 6, Operator::Substract),
+
+    ("*", 7, Operator::Multiply),
+    ("/", 7, Operator::Division),
+    ("%", 7, Operator::Modulos),
+];
+
+/* a line starting with one of these starts a new statement, synchronize stops before it */
+const STATEMENT_KEYWORDS: [&str; 6] = ["if", "while", "for", "proc", "print", "return"];
+
+/* prefix operators bind tighter than any binary one, `-a * b` is `(-a) * b` */
+const PREFIX_PRECEDENCE: u8 = 8;
+
+fn prefix_operator(raw: &str) -> Option<Operator> {
+    match raw {
+        "-" => Some(Operator::UnaryMinus),
+        "+" => Some(Operator::UnaryPass),
+        "!" => Some(Operator::LogicalNegate),
+        _ => None,
+    }
+}
+
+fn binary_operator(raw: &str) -> Option<(u8, Operator)> {
+    BINARY_OPERATORS
+        .iter()
+        .find(|(symbol, ..)| *symbol == raw)
+        .map(|(_, precedence, op)| (*precedence, op.clone()))
+}
+
+/* a loop the parser is inside of */
+struct EnclosingLoop {
+    label: Option<String>,
+
+    /* `loop` is an expression and can break with a value, `while` can't */
+    yields_value: bool,
+}
+
 pub struct Parser<'a> {
-    lexer: Lexer<'a>,
-    current_token: TokenType,
+    tokens: TokenStream<'a>,
+    current_token: Token,
+    errors: Vec<ParserError>,
+
+    /* brackets opened and not yet closed before current_token, innermost last */
+    open_brackets: Vec<char>,
+
+    /*
+        brackets of broken statements that recovery gave up on, with the depth they were
+        dropped at. Their closers are skipped without an error, innermost last
+    */
+    abandoned_brackets: Vec<(char, usize)>,
+
+    /* the line a literal that ran to the end of its line (or the file) ended on */
+    truncated_line: Option<usize>,
+
+    /* innermost last, a proc body starts with none */
+    loops: Vec<EnclosingLoop>,
+
+    /* return is only allowed in a proc body */
+    in_proc: bool,
 }
 
 #[derive(Error, Debug)]
 pub enum ParserError {
-    
+    #[error("Was expecting {expected}, found {found}")]
+    UnexpectedToken { expected: String, found: Box<Token> },
+
+    #[error("Unclosed {open:?}, was expecting a matching {close:?}")]
+    UnclosedDelimiter { open: char, close: char, span: Span },
+
+    #[error("{name} is a function, use it as {name}(...)")]
+    MissingArgumentList { name: String, span: Span },
+
+    #[error("Invalid literal, {source}")]
+    InvalidLiteral { source: ExprError, span: Span },
+
+    #[error("Only a name can be assigned to")]
+    InvalidAssignmentTarget { span: Span },
+
+    #[error("`{keyword}` outside of a loop")]
+    OutsideLoop { keyword: String, span: Span },
+
+    #[error("No enclosing loop is labelled `{label}`")]
+    UnknownLabel { label: String, span: Span },
+
+    #[error("Only `loop` can break with a value, `while` and `for` can't")]
+    BreakValueInWhile { span: Span },
+
+    #[error("`{keyword}` outside of a procedure")]
+    ReturnOutsideProc { keyword: String, span: Span },
+
+    #[error("{0}")]
+    Lexer(#[from] LexerError),
 }
 
 
 impl <'a> Parser<'a> {
-    pub fn new(mut lexer: Lexer<'a>) -> Parser {
-        let  start_val = lexer.next_token(); 
-        match start_val {
-           Ok(current_token)=>{
-               Parser { lexer, current_token} 
-           },
-           Err(e) => {
-            panic!("{}", e)
-           }
-        }
+    pub fn new(lexer: Lexer<'a>) -> Parser<'a> {
+        let tokens = TokenStream::new(lexer);
+        let placeholder = Token::new(TokenType::EOF, Span::new(tokens.current_position(), tokens.current_position()));
+        let mut parser = Parser { tokens, current_token: placeholder, errors: vec![], open_brackets: vec![], abandoned_brackets: vec![], truncated_line: None, loops: vec![], in_proc: false };
+        parser.advance();
+        parser
     }
-    
-    fn eat(&mut self, expected: TokenType) {
-        if self.current_token == expected {
-            let  start_val = self.lexer.next_token(); 
-            match start_val {
-                Ok(current_token)=>{
-                    self.current_token = current_token;
-                },
-                Err(e) => {
-                    panic!("Error -> {}", e)
+
+    /*
+        Lexer errors are recorded and the offending input skipped, so the parser always gets a
+        token. A rejected literal becomes an Invalid token, which parses as an expression so the
+        error isn't followed by another one about the missing value. Closers of abandoned
+        brackets, and a `,` right after them, are skipped.
+    */
+    fn advance(&mut self) {
+        match self.current_token.token_type {
+            TokenType::Puncutation { raw, kind: PunctuationKind::Open(_) } => self.open_brackets.push(raw),
+            /* a close also closes whatever was left open inside of it */
+            TokenType::Puncutation { raw, kind: PunctuationKind::Close(_) } => {
+                let open = Lexer::map_balance(&raw);
+                if let Some(index) = self.open_brackets.iter().rposition(|c| *c == open) {
+                    self.open_brackets.truncate(index);
                 }
             }
-        } else {
-            panic!("Expected {:?}, found {:?}", expected, self.current_token);
+            _ => {}
         }
-    }
 
+        let mut skipped_close = false;
+        loop {
+            match self.tokens.next_token() {
+                Ok(current_token) => {
+                    if self.closes_abandoned(&current_token.token_type) {
+                        skipped_close = true;
+                        continue;
+                    }
+                    if skipped_close && matches!(current_token.token_type, TokenType::Puncutation { raw: ',', .. }) {
+                        skipped_close = false;
+                        continue;
+                    }
+                    self.current_token = current_token;
+                    break;
+                }
+                Err(e) => {
+                    let literal_span = match &e {
+                        LexerError::NumericInvalid { span, .. }
+                        | LexerError::InvalidEscape { span, .. }
+                        | LexerError::InvalidCharLiteral { span, .. }
+                        | LexerError::UnmatchedInterpolationBrace { span } => Some(*span),
+                        /* the rest of the line went into the literal, closing brackets and all */
+                        LexerError::UnclosedInterpolation { span } | LexerError::UnexpectedEOF { span } => {
+                            self.truncated_line = Some(span.end.line);
+                            Some(*span)
+                        }
+                        _ => None,
+                    };
+                    self.errors.push(ParserError::Lexer(e));
 
-    fn next(&mut self) -> Expr {
-        match self.current_token.clone() {
-            TokenType::Numeric{ raw, hint } => {
-                let literal_with_error = Expr::new_literal(self.current_token.clone());
-                self.eat(TokenType::Numeric{raw, hint});
-                match literal_with_error {
-                    Ok(lit)=>{
-                        return lit
-                    },
-                    Err(e)=>{
-                        panic!("{}", e)
+                    if let Some(span) = literal_span {
+                        self.current_token = Token::new(TokenType::Invalid, span);
+                        break;
                     }
                 }
             }
-            _ => panic!("Unexpected token in factor: {:?}", self.current_token),
         }
     }
 
-    fn parse_expression(&mut self) -> Expr {
-        let mut left = self.parse_term();
-
-        while let token = self.current_token.clone() {
-            match token {
-                Token::Operators(op) => {
-                    if op == "+".to_string() {
-                        self.eat(TokenType::Operators(op));
-                    let right = self.parse_term();
-                        left = Expr::OpExpr(Box::new(OpExpr::operator(Operator::Plus, left, right)))
-                    }else if op == "-".to_string() {
-                        self.eat(TokenType::Operators(op));
-                        let right = self.parse_term();
-                        left = Expr::OpExpr(Box::new(OpExpr::operator(Operator::Substract, left, right))) 
-                    }else {
-                        break;
-                    }
+    /*
+        Panic mode recovery: skip ahead to the end of the statement that started at
+        stmt_depth, i.e the next EOL or `;` outside of any brackets opened by it, or
+        a closing bracket that belongs to an enclosing block (which is left in place).
+        A bracket the statement never closed would hide the rest of the file, so a new
+        line starting with a statement keyword ends the statement as well.
+    */
+    fn synchronize(&mut self, stmt_depth: usize) {
+        loop {
+            match self.current_token.token_type.clone() {
+                TokenType::EOF => break,
+                TokenType::EOL | TokenType::Puncutation { raw: ';', .. } if self.open_brackets.len() <= stmt_depth => {
+                    self.advance();
+                    break;
+                }
+                TokenType::EOL if matches!(
+                    self.peek(0),
+                    TokenType::Symobl(sym) if STATEMENT_KEYWORDS.contains(&sym.as_str())
+                ) => {
+                    self.abandon_brackets(stmt_depth);
+                    self.advance();
+                    break;
+                }
+                TokenType::Puncutation { raw, kind: PunctuationKind::Close(_) } if self.closes_enclosing(raw, stmt_depth) => {
+                    self.open_brackets.truncate(stmt_depth);
+                    break;
                 }
-                _ => break,
+                _ => self.advance(),
             }
         }
+    }
 
-        left
-    }
-
-    fn parse_term(&mut self) -> Expr {
-        let mut left = self.parse_factor();
-        // println!("{:?}", left);
-
-        while let token = self.current_token.clone() {
-            match token {
-                Token::Operators(op) => {
-                    if op == "/".to_string() {
-                        self.eat(TokenType::Operators(op));
-                        let right = self.parse_factor();
-                        left = Expr::OpExpr(Box::new(OpExpr::operator(Operator::Division, left, right))) 
-                    }else if op == "*".to_string() {
-                        self.eat(TokenType::Operators(op));
-                        let right = self.parse_factor();
-                        left = Expr::OpExpr(Box::new(OpExpr::operator(Operator::Multiply, left, right)))
-                    }else if op == "%".to_string() {
-                        self.eat(TokenType::Operators(op));
-                        let right = self.parse_factor();
-                        left = Expr::OpExpr(Box::new(OpExpr::operator(Operator::Modulos, left, right))) 
-                    }else if op == "==".to_string() {
-                        self.eat(TokenType::Operators(op));
-                        let right = self.parse_factor();
-                        left = Expr::OpExpr(Box::new(OpExpr::operator(Operator::Equal, left, right))) 
-                    }else {
-                        break;
-                    }
+    /* the brackets opened since stmt_depth won't be closed by the parser, their closers are skipped */
+    fn abandon_brackets(&mut self, stmt_depth: usize) {
+        let dropped = self.open_brackets.split_off(stmt_depth.min(self.open_brackets.len()));
+        self.abandoned_brackets.extend(dropped.into_iter().map(|open| (open, stmt_depth)));
+    }
+
+    /* whether token closes the innermost abandoned bracket, and not one opened after it was dropped */
+    fn closes_abandoned(&mut self, token: &TokenType) -> bool {
+        let close = match token {
+            TokenType::Puncutation { raw, kind: PunctuationKind::Close(_) } => *raw,
+            _ => return false,
+        };
+        let open = Lexer::map_balance(&close);
+
+        match self.abandoned_brackets.last() {
+            Some(&(abandoned, depth)) if abandoned == open => {
+                let reopened = self.open_brackets.get(depth..).unwrap_or(&[]).contains(&open);
+                if !reopened {
+                    self.abandoned_brackets.pop();
                 }
-                _ => {break},
+                !reopened
             }
+            _ => false,
         }
+    }
+
+    /*
+        A literal ran to the end of line, so the statement's error is about the missing rest of
+        it. It isn't reported and the statement ends with that line.
+    */
+    fn skip_truncated_line(&mut self, line: usize, stmt_depth: usize) {
+        while self.current_token.token_type != TokenType::EOF && self.current_token.span.start.line <= line {
+            self.advance();
+        }
+        self.abandon_brackets(stmt_depth);
+    }
 
-        left
+    /* whether close matches a bracket opened before stmt_depth, rather than one the statement opened */
+    fn closes_enclosing(&self, close: char, stmt_depth: usize) -> bool {
+        let open = Lexer::map_balance(&close);
+        match self.open_brackets.iter().rposition(|c| *c == open) {
+            Some(index) => index < stmt_depth,
+            None => true,
+        }
     }
 
+    /* the token n places after current_token */
+    fn peek(&mut self, n: usize) -> &TokenType {
+        &self.tokens.peek_nth(n).token_type
+    }
 
-    fn parse_factor(&mut self) -> Expr {
-        match self.current_token.clone() {
-            TokenType::Numeric{raw, hint} => {
-               let literal_with_error = Expr::new_literal(self.current_token.clone());
-                match literal_with_error {
-                    Ok(lit)=>{
-                        self.eat(TokenType::Numeric { raw, hint});
-                        lit
-                    },
-                    Err(e)=>{
-                        panic!("Panic Factor: {}", e)
-                    }
-                }
+    /* the first token after current_token that isn't a newline */
+    fn peek_past_eol(&mut self) -> &TokenType {
+        let mut n = 0;
+        while *self.peek(n) == TokenType::EOL {
+            n += 1;
+        }
+        self.peek(n)
+    }
+
+    fn unexpected(&self, expected: &str) -> ParserError {
+        ParserError::UnexpectedToken {
+            expected: expected.to_string(),
+            found: Box::new(self.current_token.clone()),
+        }
+    }
+
+    fn eat(&mut self, expected: TokenType) -> Result<Token, ParserError> {
+        if self.current_token.token_type == expected {
+            let eaten = self.current_token.clone();
+            self.advance();
+            Ok(eaten)
+        } else {
+            Err(self.unexpected(&expected.to_string()))
+        }
+    }
+
+    /* eats the closing `close` matching an `open` found at open_span */
+    fn eat_close(&mut self, open: char, close: char, depth: i32, open_span: Span) -> Result<Token, ParserError> {
+        match self.current_token.token_type {
+            TokenType::Puncutation { raw, kind: PunctuationKind::Close(d) } if raw == close && d == depth => {
+                self.eat(TokenType::Puncutation { raw, kind: PunctuationKind::Close(d) })
+            }
+            TokenType::EOF => Err(ParserError::UnclosedDelimiter { open, close, span: open_span }),
+            _ => Err(self.unexpected(&format!("`{}`", close))),
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<Expr, ParserError> {
+        self.parse_binary(0)
+    }
+
+    /* precedence climbing over BINARY_OPERATORS, only binds operators at least as tight as min_precedence */
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, ParserError> {
+        let mut left = self.parse_factor()?;
+
+        while let TokenType::Operators(raw) = &self.current_token.token_type {
+            let (precedence, op) = match binary_operator(raw) {
+                Some(entry) => entry,
+                None => break,
+            };
+
+            if precedence < min_precedence {
+                break;
+            }
+
+            self.eat(self.current_token.token_type.clone())?;
+
+            /* the right side only takes tighter operators, so `a - b - c` is `(a - b) - c` */
+            let right = self.parse_binary(precedence + 1)?;
+
+            left = Expr::OpExpr(Box::new(OpExpr::operator(op, left, right)));
+        }
+
+        Ok(left)
+    }
+
+
+    fn parse_factor(&mut self) -> Result<Expr, ParserError> {
+        let start_span = self.current_token.span;
+
+        match self.current_token.token_type.clone() {
+            TokenType::Numeric{..} => self.parse_literal(),
+            TokenType::String(_) | TokenType::Char(_) => self.parse_literal(),
+            TokenType::InterpolatedString(parts) => {
+                self.advance();
+                self.parse_interpolation(parts, start_span)
             },
-            Token::Puncutation{raw, kind} => {
-                if raw == '('{ 
-                    match kind {
-                        PunctuationKind::Open(depth) => {        
-                            self.eat(TokenType::Puncutation { raw: raw, kind: kind });
-                            let expr = self.parse_expression();
-                            self.remove_eol();
-                            assert_eq!(self.current_token, Token::Puncutation{raw: ')', kind:PunctuationKind::Close(depth)});
-                            self.eat(TokenType::Puncutation { raw: ')', kind: PunctuationKind::Close(depth) });
-                            expr
-                        }
-                        _=>{
-                            panic!("Not Allowed token: {:?}", self.current_token)
-                        }
+            TokenType::Symobl(sym) if sym == "true" || sym == "false" => self.parse_literal(),
+            TokenType::Puncutation{raw: '(', kind: PunctuationKind::Open(depth)} => {
+                self.eat(TokenType::Puncutation { raw: '(', kind: PunctuationKind::Open(depth) })?;
+                let expr = self.parse_expression()?;
+                self.remove_eol()?;
+                self.eat_close('(', ')', depth, start_span)?;
+                Ok(expr)
+            },
+            TokenType::Operators(raw) if prefix_operator(&raw).is_some() => {
+                self.eat(TokenType::Operators(raw.clone()))?;
+
+                /* `-5` is folded into the literal itself, so the full range of the type can be written */
+                if let TokenType::Numeric { raw: number, hint, suffix } = self.current_token.token_type.clone() {
+                    if raw == "-" {
+                        let negative = Token::new(
+                            TokenType::Numeric { raw: format!("-{}", number), hint, suffix },
+                            start_span.to(self.current_token.span),
+                        );
+                        let lit = Expr::new_literal(negative)
+                            .map_err(|source| ParserError::InvalidLiteral { source, span: start_span.to(self.current_token.span) })?;
+                        self.advance();
+                        return Ok(lit);
                     }
-                }else{
-                    panic!("Error on token: {:?}", self.current_token)
                 }
+
+                let operand = self.parse_binary(PREFIX_PRECEDENCE)?;
+                let op = prefix_operator(&raw).expect("checked by the match guard");
+                Ok(Expr::OpExpr(Box::new(OpExpr::unary(op, operand, start_span))))
+            },
+            TokenType::Symobl(sym) if sym == "loop" => self.parse_loop(None, start_span),
+            TokenType::Identifier(label) if self.is_label_of("loop") => {
+                self.advance();
+                self.advance();
+                self.parse_loop(Some(label), start_span)
+            },
+            TokenType::Identifier(i) if matches!(self.peek(0), TokenType::Puncutation { raw: '(', .. }) => {
+                self.advance();
+                self.parse_call(&i, start_span)
             },
             TokenType::Identifier(i) => {
-                if i.is_ascii(){
-                    self.eat(TokenType::Identifier(i.clone()));
-                    let op_symbol = Expr::OpLiteral(Box::new(Literal::Symbol(i.clone())));
-                    
-                    // println!("{:?}", op_symbol);
-                    match self.current_token.clone() {
-                        TokenType::Operators(op) => {
-                            if op == String::from("=") {
-                                self.eat(TokenType::Operators("=".to_string()));
-                                let next_expers = self.parse_expression();
-                                Expr::OpExpr(Box::new(OpExpr { op: Operator::Assignment, args: vec![op_symbol, next_expers] }))
-                            }else {
-                                op_symbol
-                            }
-                        },
-                        TokenType::EOF | TokenType::EOL => {
-                            op_symbol
-                        },
-                        _ => {
-                            panic!("The Token type {:?} used after identifier {:?} is not correct.", self.current_token, i)
-                        }
-                    }
-                }else {
-                    panic!("The {:?} identefier is not allowed.", i)
-                }
+                self.eat(TokenType::Identifier(i.clone()))?;
+                Ok(Expr::OpLiteral(Box::new(Literal::Symbol(i)), start_span))
             },
-            TokenType::Symobl(sym) => {
-                self.eat(TokenType::Symobl(sym.clone()));
-
-                match sym.as_ref() {
-                    "print" => {
-                        self.parse_function("print")
-                    }
-                    "if" => {
-                        self.parse_function("if")
-                    }
-                    _ => {
-                        unimplemented!()
-                    }
-                }
+            TokenType::Symobl(sym) if sym == "print" => {
+                self.advance();
+                self.parse_call(&sym, start_span)
             },
-            TokenType::Comment =>{
-                self.eat(TokenType::Comment);
-                Expr::OPComment
-            }
             TokenType::EOL => {
-                self.eat(TokenType::EOL);
+                self.eat(TokenType::EOL)?;
                 self.parse_expression()
             }
-            _ => panic!("Unexpected token: {:?}", self.current_token),
+            /* never compiled, the lexer error it stands for is already recorded */
+            TokenType::Invalid => {
+                self.advance();
+                Ok(Expr::OpLiteral(Box::new(Literal::Integer(0)), start_span))
+            }
+            _ => Err(self.unexpected("an expression")),
         }
     }
 
-    fn remove_eol(&mut self){
-        loop {
-            match self.current_token {
-                TokenType::EOL=>{
-                    self.eat(TokenType::EOL)
+    fn parse_literal(&mut self) -> Result<Expr, ParserError> {
+        let span = self.current_token.span;
+        let lit = Expr::new_literal(self.current_token.clone())
+            .map_err(|source| ParserError::InvalidLiteral { source, span })?;
+        self.advance();
+        Ok(lit)
+    }
+
+    /* "a {x} b" becomes a concatenation of "a ", the formatted x and " b" */
+    fn parse_interpolation(&mut self, parts: Vec<StringPart>, span: Span) -> Result<Expr, ParserError> {
+        let mut pieces = vec![];
+
+        for part in parts {
+            match part {
+                StringPart::Text(text) => pieces.push(Expr::OpLiteral(Box::new(Literal::String(text)), span)),
+                StringPart::Expr { source, start, keywords } => {
+                    let mut embedded = Parser::new(Lexer::new(&source).with_keywords(keywords).with_start(start));
+                    let expr = embedded.parse_expression();
+                    let end = embedded.remove_eol().and_then(|_| match embedded.current_token.token_type {
+                        TokenType::EOF => Ok(()),
+                        _ => Err(embedded.unexpected("`}`")),
+                    });
+                    self.errors.append(&mut embedded.errors);
+
+                    let expr = expr?;
+                    end?;
+                    let expr_span = expr.span();
+                    pieces.push(Expr::OpExpr(Box::new(OpExpr::function_op(Operator::Format, expr, expr_span))));
                 }
-                (_)=>{
+            }
+        }
+
+        Ok(Expr::OpExpr(Box::new(OpExpr::concat(pieces, span))))
+    }
+
+    fn remove_eol(&mut self) -> Result<(), ParserError> {
+        while self.current_token.token_type == TokenType::EOL {
+            self.eat(TokenType::EOL)?;
+        }
+        Ok(())
+    }
+
+    /* `name(a, b, ...)`, called with current_token on the `(` */
+    fn parse_call(&mut self, function_name: &str, name_span: Span) -> Result<Expr, ParserError> {
+        let depth = match self.current_token.token_type {
+            TokenType::Puncutation { raw: '(', kind: PunctuationKind::Open(depth) } => depth,
+            _ => {
+                return Err(ParserError::MissingArgumentList {
+                    name: function_name.to_string(),
+                    span: name_span,
+                })
+            }
+        };
+
+        let open_span = self.current_token.span;
+        self.advance();
+        self.remove_eol()?;
+
+        let mut args = vec![];
+        if !matches!(self.current_token.token_type, TokenType::Puncutation { raw: ')', .. }) {
+            loop {
+                args.push(self.parse_expression()?);
+                self.remove_eol()?;
+
+                if let TokenType::Puncutation { raw: ',', .. } = self.current_token.token_type {
+                    self.advance();
+                    self.remove_eol()?;
+                } else {
                     break;
                 }
             }
         }
+
+        let close = self.eat_close('(', ')', depth, open_span)?;
+        Ok(Expr::OpExpr(Box::new(OpExpr::call(function_name, args, name_span.to(close.span)))))
+    }
+
+    /* a statement and its terminator, which is a `;`, a newline, or left alone when it's a `}` or the end of file */
+    fn parse_statement(&mut self) -> Result<Stmt, ParserError> {
+        let stmt = match self.current_token.token_type.clone() {
+            TokenType::Symobl(sym) if sym == "if" => self.parse_if()?,
+            TokenType::Symobl(sym) if sym == "while" => self.parse_while(None)?,
+            TokenType::Identifier(label) if self.is_label_of("while") => {
+                self.advance();
+                self.advance();
+                self.parse_while(Some(label))?
+            }
+            TokenType::Symobl(sym) if sym == "for" => self.parse_for(None)?,
+            TokenType::Identifier(label) if self.is_label_of("for") => {
+                self.advance();
+                self.advance();
+                self.parse_for(Some(label))?
+            }
+            TokenType::Symobl(sym) if sym == "break" => self.parse_break()?,
+            TokenType::Symobl(sym) if sym == "continue" => self.parse_continue()?,
+            TokenType::Symobl(sym) if sym == "return" => self.parse_return()?,
+            TokenType::Symobl(sym) if sym == "proc" => self.parse_proc()?,
+            _ => self.parse_assignment()?,
+        };
+
+        match self.current_token.token_type {
+            TokenType::EOL | TokenType::Puncutation { raw: ';', .. } => self.advance(),
+            TokenType::EOF | TokenType::Puncutation { kind: PunctuationKind::Close(_), .. } => {}
+            _ => return Err(self.unexpected("`;` or a new line")),
+        }
+
+        Ok(stmt)
     }
 
-    fn parse_function(&mut self, function_name: &str) -> Expr {
-        match self.current_token.clone() {
-            TokenType::Puncutation { raw, kind } => {
-                if raw == '(' {
-                    match kind {
-                        PunctuationKind::Open(depth) => {
-                            self.eat(TokenType::Puncutation { raw: raw, kind: kind });
-                            let expr = self.parse_expression();
-                            self.remove_eol();
-                            assert_eq!(self.current_token, Token::Puncutation{raw: ')', kind:PunctuationKind::Close(depth)});
-                            self.eat(TokenType::Puncutation { raw: ')', kind: PunctuationKind::Close(depth) });
-                            match self.current_token.clone()  {
-                                TokenType::EOL =>{
-                                    self.eat(TokenType::EOL);
-                                    Expr::OpExpr(Box::new(OpExpr::function_op(Operator::Call(function_name.to_string()), expr)))
-                                }
-                                TokenType::EOF =>{
-                                    self.eat(TokenType::EOF);
-                                    Expr::OpExpr(Box::new(OpExpr::function_op(Operator::Call(function_name.to_string()), expr)))
-                                }
-                                TokenType::Puncutation { raw, kind }=>{
-                                    if raw == '{' {
-                                        match kind {
-                                            PunctuationKind::Open(depth) =>{
-                                                self.eat(TokenType::Puncutation { raw: raw, kind: kind });
-                                                let new_expr = self.parse_expression();
-                                                self.remove_eol();
-                                                assert_eq!(self.current_token, Token::Puncutation{raw: '}', kind:PunctuationKind::Close(depth)});
-                                                self.eat(TokenType::Puncutation { raw: '}', kind: PunctuationKind::Close(depth) });
-                                                Expr::OpExpr(Box::new(OpExpr::function_op(Operator::Define(expr), new_expr)))
-                                                }
-                                            _=> unimplemented!()
-                                        }
-                                    }else if  raw == '}' {
-                                        expr
-                                    }else{
-                                        panic!("This is not correct {}", raw)
-                                    }
-                                }
-                                _ =>unimplemented!()
+    /* `x := value`, `x = value`, `x += value` or just an expression */
+    fn parse_assignment(&mut self) -> Result<Stmt, ParserError> {
+        let target = self.parse_expression()?;
+
+        let raw = match &self.current_token.token_type {
+            TokenType::Operators(raw) if matches!(raw.as_str(), ":=" | "=" | "+=" | "-=" | "%=") => raw.clone(),
+            _ => return Ok(Stmt::Expr(target)),
+        };
+
+        let name = match &target {
+            Expr::OpLiteral(lit, _) => match &**lit {
+                Literal::Symbol(name) => name.clone(),
+                _ => return Err(ParserError::InvalidAssignmentTarget { span: target.span() }),
+            },
+            _ => return Err(ParserError::InvalidAssignmentTarget { span: target.span() }),
+        };
+
+        self.advance();
+        let value = self.parse_expression()?;
+        let span = target.span().to(value.span());
+
+        let value = match raw.as_str() {
+            "+=" => Expr::OpExpr(Box::new(OpExpr::operator(Operator::Plus, target, value))),
+            "-=" => Expr::OpExpr(Box::new(OpExpr::operator(Operator::Substract, target, value))),
+            "%=" => Expr::OpExpr(Box::new(OpExpr::operator(Operator::Modulos, target, value))),
+            _ => value,
+        };
+
+        if raw == ":=" {
+            Ok(Stmt::Let { name, value, span })
+        } else {
+            Ok(Stmt::Assign { name, value, span })
+        }
+    }
+
+    /* statements up to a closing bracket or the end of file, errors are recorded and skipped over */
+    fn parse_statements(&mut self) -> Vec<Stmt> {
+        let mut stmts = vec![];
+
+        loop {
+            match self.current_token.token_type {
+                TokenType::EOF | TokenType::Puncutation { kind: PunctuationKind::Close(_), .. } => break,
+                TokenType::EOL | TokenType::Puncutation { raw: ';', .. } => self.advance(),
+                _ => {
+                    let stmt_depth = self.open_brackets.len();
+                    let parsed = self.parse_statement();
+                    match (parsed, self.truncated_line.take()) {
+                        (Ok(stmt), _) => stmts.push(stmt),
+                        (Err(_), Some(line)) => self.skip_truncated_line(line, stmt_depth),
+                        (Err(e), None) => {
+                            let error_offset = self.current_token.span.start.offset;
+                            self.errors.push(e);
+                            self.synchronize(stmt_depth);
+
+                            /* always make progress, unless stopped on the `}` of the enclosing block */
+                            if self.current_token.span.start.offset == error_offset
+                                && !matches!(
+                                    self.current_token.token_type,
+                                    TokenType::EOF | TokenType::Puncutation { kind: PunctuationKind::Close(_), .. }
+                                )
+                            {
+                                self.advance();
                             }
                         }
-                        PunctuationKind::Seperator =>{
-                            self.eat(TokenType::Puncutation { raw: raw, kind: kind });
-                            self.parse_expression()
-                        }
-                        _=>{panic!("Print is a function, use it as print(\"Hello World\")")}
                     }
-                }else {
-                    unimplemented!()
                 }
             }
-            _ => {panic!("Print is a function, use it as print(\"Hello World\")")}
         }
+
+        stmts
     }
 
-    pub fn walk(&mut self) -> Program { 
+    /* `{ statements }` */
+    fn parse_block(&mut self) -> Result<Block, ParserError> {
+        let open_span = self.current_token.span;
+        let depth = match self.current_token.token_type {
+            TokenType::Puncutation { raw: '{', kind: PunctuationKind::Open(depth) } => depth,
+            _ => return Err(self.unexpected("`{`")),
+        };
+        self.advance();
+
+        let stmts = self.parse_statements();
+        let close = self.eat_close('{', '}', depth, open_span)?;
+        Ok(Block { stmts, span: open_span.to(close.span) })
+    }
+
+    /* `if condition { ... } else if condition { ... } else { ... }`, an else if is an if inside the else block */
+    fn parse_if(&mut self) -> Result<Stmt, ParserError> {
+        let start_span = self.current_token.span;
+        self.advance();
+
+        let condition = self.parse_expression()?;
+        let then_block = self.parse_block()?;
+        let mut span = start_span.to(then_block.span);
+
+        /* `else` may also start the next line */
+        if self.current_token.token_type == TokenType::EOL
+            && matches!(self.peek_past_eol(), TokenType::Symobl(sym) if sym == "else")
+        {
+            self.remove_eol()?;
+        }
+
+        let else_block = match &self.current_token.token_type {
+            TokenType::Symobl(sym) if sym == "else" => {
+                self.advance();
+
+                let block = match &self.current_token.token_type {
+                    TokenType::Symobl(sym) if sym == "if" => {
+                        let else_if = self.parse_if()?;
+                        Block { span: else_if.span(), stmts: vec![else_if] }
+                    }
+                    _ => self.parse_block()?,
+                };
+                span = span.to(block.span);
+                Some(block)
+            }
+            _ => None,
+        };
+
+        Ok(Stmt::If { condition, then_block, else_block, span })
+    }
+
+    /* current_token is the `name` of `name: keyword` */
+    fn is_label_of(&mut self, keyword: &str) -> bool {
+        matches!(self.peek(0), TokenType::Operators(op) if op == ":")
+            && matches!(self.peek(1), TokenType::Symobl(sym) if sym == keyword)
+    }
+
+    /* the body of a loop, with the loop known to break / continue inside of it */
+    fn parse_loop_body(&mut self, label: Option<String>, yields_value: bool) -> Result<Block, ParserError> {
+        self.loops.push(EnclosingLoop { label, yields_value });
+        let body = self.parse_block();
+        self.loops.pop();
+        body
+    }
+
+    /* `loop { ... }`, called on the `loop` */
+    fn parse_loop(&mut self, label: Option<String>, start_span: Span) -> Result<Expr, ParserError> {
+        self.advance();
+
+        let body = self.parse_loop_body(label.clone(), true)?;
+        let span = start_span.to(body.span);
+        Ok(Expr::Loop(Box::new(LoopExpr { label, body, span })))
+    }
+
+    /* `while condition { ... }`, called on the `while` */
+    fn parse_while(&mut self, label: Option<String>) -> Result<Stmt, ParserError> {
+        let start_span = self.current_token.span;
+        self.advance();
+
+        let condition = self.parse_expression()?;
+        let body = self.parse_loop_body(label.clone(), false)?;
+        let span = start_span.to(body.span);
+        Ok(Stmt::While { label, condition, body, span })
+    }
+
+    /* `for i in start..end step n { ... }`, called on the `for` */
+    fn parse_for(&mut self, label: Option<String>) -> Result<Stmt, ParserError> {
+        let start_span = self.current_token.span;
+        self.advance();
+
+        let variable = match &self.current_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => return Err(self.unexpected("a loop variable")),
+        };
+        self.advance();
+        self.eat(TokenType::Symobl("in".to_string()))?;
+
+        let start = self.parse_expression()?;
+        let inclusive = match &self.current_token.token_type {
+            TokenType::Operators(op) if op == ".." || op == "..=" => op == "..=",
+            _ => return Err(self.unexpected("`..` or `..=`")),
+        };
+        self.advance();
+        let end = self.parse_expression()?;
+
+        let step = match &self.current_token.token_type {
+            TokenType::Symobl(sym) if sym == "step" => {
+                self.advance();
+                Some(self.parse_expression()?)
+            }
+            _ => None,
+        };
+
+        let body = self.parse_loop_body(label.clone(), false)?;
+        let span = start_span.to(body.span);
+        Ok(Stmt::For { label, variable, start, end, inclusive, step, body, span })
+    }
+
+    /* nothing else of the statement follows */
+    fn at_statement_end(&self) -> bool {
+        matches!(
+            self.current_token.token_type,
+            TokenType::EOL
+                | TokenType::EOF
+                | TokenType::Puncutation { raw: ';', .. }
+                | TokenType::Puncutation { kind: PunctuationKind::Close(_), .. }
+        )
+    }
+
+    /* `break`, `break value`, `break label` or `break label value` */
+    fn parse_break(&mut self) -> Result<Stmt, ParserError> {
+        let mut span = self.current_token.span;
+        let keyword = self.current_token.spelling.clone().unwrap_or_else(|| "break".to_string());
+        self.advance();
+
+        if self.loops.is_empty() {
+            return Err(ParserError::OutsideLoop { keyword, span });
+        }
+
+        /* a name is only a label when a loop around has it, otherwise it's the value */
+        let label = match &self.current_token.token_type {
+            TokenType::Identifier(name) if self.loops.iter().any(|l| l.label.as_ref() == Some(name)) => {
+                let name = name.clone();
+                span = span.to(self.current_token.span);
+                self.advance();
+                Some(name)
+            }
+            _ => None,
+        };
+
+        let value = if self.at_statement_end() { None } else { Some(self.parse_expression()?) };
+
+        if let Some(value) = &value {
+            span = span.to(value.span());
+
+            let target = match &label {
+                Some(label) => self.loops.iter().rev().find(|l| l.label.as_ref() == Some(label)),
+                None => self.loops.last(),
+            };
+            if matches!(target, Some(target) if !target.yields_value) {
+                return Err(ParserError::BreakValueInWhile { span });
+            }
+        }
+
+        Ok(Stmt::Break { label, value, span })
+    }
+
+    /* `continue` or `continue label` */
+    fn parse_continue(&mut self) -> Result<Stmt, ParserError> {
+        let mut span = self.current_token.span;
+        let keyword = self.current_token.spelling.clone().unwrap_or_else(|| "continue".to_string());
+        self.advance();
+
+        if self.loops.is_empty() {
+            return Err(ParserError::OutsideLoop { keyword, span });
+        }
+
+        let label = match &self.current_token.token_type {
+            TokenType::Identifier(name) => {
+                let name = name.clone();
+                span = span.to(self.current_token.span);
+                self.advance();
+
+                if !self.loops.iter().any(|l| l.label.as_ref() == Some(&name)) {
+                    return Err(ParserError::UnknownLabel { label: name, span });
+                }
+                Some(name)
+            }
+            _ => None,
+        };
+
+        Ok(Stmt::Continue { label, span })
+    }
+
+    /* `return` or `return value` */
+    fn parse_return(&mut self) -> Result<Stmt, ParserError> {
+        let span = self.current_token.span;
+        let keyword = self.current_token.spelling.clone().unwrap_or_else(|| "return".to_string());
+        self.advance();
+
+        if !self.in_proc {
+            return Err(ParserError::ReturnOutsideProc { keyword, span });
+        }
+
+        if self.at_statement_end() {
+            return Ok(Stmt::Return { value: None, span });
+        }
+
+        let value = self.parse_expression()?;
+        let span = span.to(value.span());
+        Ok(Stmt::Return { value: Some(value), span })
+    }
+
+    /* `proc name(a, b) { ... }` */
+    fn parse_proc(&mut self) -> Result<Stmt, ParserError> {
+        let start_span = self.current_token.span;
+        self.advance();
+
+        let name = match &self.current_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => return Err(self.unexpected("a procedure name")),
+        };
+        self.advance();
+
+        let open_span = self.current_token.span;
+        let depth = match self.current_token.token_type {
+            TokenType::Puncutation { raw: '(', kind: PunctuationKind::Open(depth) } => depth,
+            _ => return Err(ParserError::MissingArgumentList { name, span: start_span.to(open_span) }),
+        };
+        self.advance();
+
+        let mut params = vec![];
+        while let TokenType::Identifier(param) = &self.current_token.token_type {
+            params.push(param.clone());
+            self.advance();
+
+            if let TokenType::Puncutation { raw: ',', .. } = self.current_token.token_type {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        self.eat_close('(', ')', depth, open_span)?;
+
+        /* loops around the definition can't be broken out of from inside it */
+        let enclosing_loops = std::mem::take(&mut self.loops);
+        let enclosing_in_proc = std::mem::replace(&mut self.in_proc, true);
+        let body = self.parse_block();
+        self.loops = enclosing_loops;
+        self.in_proc = enclosing_in_proc;
+
+        let body = body?;
+        let span = start_span.to(body.span);
+        Ok(Stmt::Proc { name, params, body, span })
+    }
+
+    pub fn walk(&mut self) -> Result<Program, Vec<ParserError>> {
         let mut program = Program::new();
-        
-        let mut expressions = self.parse_expression();
+
         loop {
-            match self.current_token {
-                TokenType::EOL => {
-                    self.eat(TokenType::EOL);
-                }
-                TokenType::EOF => {
-                    program.exprs.push(expressions);
-                    break;
-                },
+            program.stmts.append(&mut self.parse_statements());
+
+            /* a closing bracket nothing at the top level opened */
+            match self.current_token.token_type {
+                TokenType::EOF => break,
                 _ => {
-                    program.exprs.push(expressions);
-                    if self.current_token == TokenType::EOF {
-                        break;
-                    }
-                    expressions = self.parse_expression();
+                    let error = self.unexpected("a statement");
+                    self.errors.push(error);
+                    self.advance();
                 }
             }
         }
-        program
+
+        if self.errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
     }
 }
-
@@ -0,0 +1,117 @@
+/*
+    Runs the danfe binary on programs with mistakes in them and checks which errors it
+    reports, from the json output. Nothing gets assembled, so these don't need nasm.
+*/
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/* what danfe writes to stderr for source */
+fn stderr(name: &str, source: &str, args: &[&str]) -> String {
+    let dir: PathBuf = std::env::temp_dir().join(format!("danfe-diagnostics-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).expect("temp dir is writable");
+    fs::write(dir.join("main.df"), source).expect("temp dir is writable");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_danfe"))
+        .args(["file", "main.df"])
+        .args(args)
+        .current_dir(&dir)
+        .output()
+        .expect("danfe runs");
+    let _ = fs::remove_dir_all(&dir);
+
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+    stderr
+}
+
+/* the code of every diagnostic, in the order they were reported */
+fn error_codes(name: &str, source: &str) -> Vec<String> {
+    stderr(name, source, &["--error-format=json"])
+        .lines()
+        .filter_map(|line| line.split("\"code\":\"").nth(1))
+        .map(|rest| rest.chars().take_while(|c| *c != '"').collect())
+        .collect()
+}
+
+#[test]
+fn reports_errors_after_an_unclosed_bracket() {
+    /* the `(` runs on into the next line, so its error is the one at `print(1 +)` */
+    let codes = error_codes("unclosed", "x := (1 +\nprint(1 +)\nif { }\nprint(3 *)\nreturn 1\n");
+    assert_eq!(codes, ["E0201", "E0201", "E0201", "E0209"]);
+}
+
+#[test]
+fn recovers_at_a_close_of_the_enclosing_block() {
+    let codes = error_codes("enclosing", "while 1 {\n    x := (1 +\n}\nprint(2 +)\n");
+    assert_eq!(codes, ["E0201", "E0201"]);
+}
+
+#[test]
+fn skips_closers_of_abandoned_brackets() {
+    let codes = error_codes("abandoned", "f := (1 +, {\n    print(1)\n})\nprint(2 +)\n");
+    assert_eq!(codes, ["E0201", "E0201"]);
+}
+
+#[test]
+fn unclosed_strings_are_reported_once() {
+    assert_eq!(error_codes("unclosed-string", "print(\"abc)\n"), ["E0105"]);
+    assert_eq!(error_codes("unclosed-interpolation", "print(\"unclosed {x\")\ny := 2\nprint(y)\n"), ["E0110"]);
+}
+
+#[test]
+fn rejected_literals_are_reported_once() {
+    assert_eq!(error_codes("escape", "print(\"\\x80\", 1)\n"), ["E0107"]);
+    assert_eq!(error_codes("number", "print(0x, 1)\n"), ["E0102"]);
+    assert_eq!(error_codes("char", "print('ab', 1)\n"), ["E0106"]);
+}
+
+#[test]
+fn caret_lines_up_after_tabs() {
+    let rendered = stderr("tabs", "\tprint(1 +)\n", &[]);
+    assert!(rendered.contains("1 | \tprint(1 +)\n  | \t         ^\n"), "{}", rendered);
+}
+
+#[test]
+fn invalid_escape_inside_interpolation_is_an_error() {
+    assert_eq!(error_codes("embedded-escape", "print(\"a {\"\\q\"} b\")\n"), ["E0107"]);
+}
+
+#[test]
+fn rejects_a_literal_zero_step() {
+    assert_eq!(error_codes("zero-step", "for i in 0..3 step 0 { print(i) }\n"), ["E0309"]);
+}
+
+#[test]
+fn caret_lines_up_after_combining_marks() {
+    /* the virama in संख्या takes no room, so it's four columns wide */
+    let rendered = stderr("combining", "संख्या := (१ +)\n", &["--keywords=nepali"]);
+    assert!(rendered.contains("1 | संख्या := (१ +)\n  |             ^\n"), "{}", rendered);
+}
+
+#[test]
+fn keywords_keep_their_spelling_in_messages() {
+    let rendered = stderr("spelling", "#!keywords nepali\nx := (1 यदि)\nरोक\n", &[]);
+    assert!(rendered.contains("Was expecting `)`, found keyword `यदि`"), "{}", rendered);
+    assert!(rendered.contains("`रोक` outside of a loop"), "{}", rendered);
+}
+
+#[test]
+fn nepali_keywords_need_to_be_turned_on() {
+    assert_eq!(error_codes("english-keywords", "छाप(१)\n"), ["E0304"]);
+}
+
+#[test]
+fn reports_literals_that_overflow_their_type() {
+    let cases = [
+        ("print(300u8)\n", "`300` doesn't fit in u8, which holds 0..=255"),
+        ("print(128i8)\n", "`128` doesn't fit in i8, which holds -128..=127"),
+        ("print(-5u64)\n", "`-5` doesn't fit in u64"),
+        ("print(9223372036854775808)\n", "`9223372036854775808` doesn't fit in i64"),
+    ];
+    for (source, message) in cases {
+        assert_eq!(error_codes("overflow", source), ["E0204"], "{}", source);
+        let rendered = stderr("overflow", source, &[]);
+        assert!(rendered.contains(message), "{}", rendered);
+    }
+}
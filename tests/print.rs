@@ -0,0 +1,249 @@
+/*
+    Compiles small programs with the danfe binary and checks what they print. They need
+    nasm and ld, so they only run with `cargo test -- --include-ignored`.
+*/
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/* what danfe outputs for source, each test gets its own directory for the output */
+fn compile_and_run(name: &str, source: &str, args: &[&str]) -> Output {
+    let dir: PathBuf = std::env::temp_dir().join(format!("danfe-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).expect("temp dir is writable");
+    fs::write(dir.join("main.df"), source).expect("temp dir is writable");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_danfe"))
+        .args(["file", "main.df"])
+        .args(args)
+        .current_dir(&dir)
+        .output()
+        .expect("danfe runs");
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+/* the stdout of the compiled program */
+fn run(name: &str, source: &str) -> String {
+    run_with_args(name, source, &[])
+}
+
+fn run_with_args(name: &str, source: &str, args: &[&str]) -> String {
+    let output = compile_and_run(name, source, args);
+    assert!(output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).trim_end().to_string()
+}
+
+/* the stderr of a compiled program that stopped with a runtime error */
+fn run_failing(name: &str, source: &str) -> String {
+    String::from_utf8_lossy(&compile_and_run(name, source, &[]).stderr).to_string()
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn prints_positive_numbers() {
+    assert_eq!(run("positive", "print(0, 7, 1000000, 9223372036854775807)"), "0\n7\n1000000\n9223372036854775807");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn prints_negative_numbers() {
+    assert_eq!(run("negative", "print(0 - 5, -1 * 7, -10)"), "-5\n-7\n-10");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn prints_i64_min() {
+    assert_eq!(run("min", "print(-9223372036854775808, -9223372036854775807 - 1)"), "-9223372036854775808\n-9223372036854775808");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn prints_results_of_signed_arithmetic() {
+    assert_eq!(run("arithmetic", "print((0 - 7) / 2, (0 - 7) % 3, 6 * (0 - 7))"), "-3\n-1\n-42");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn prints_floats() {
+    assert_eq!(run("floats", "print(3.14, 2.0, -0.5, 1.0 / 3.0, 1e12, 1e-5)"), "3.14\n2.0\n-0.5\n0.333333\n1.0e12\n1.0e-5");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn prints_infinity_and_nan() {
+    assert_eq!(run("infinity", "print(1.0 / 0.0, -1.0 / 0.0, 0.0 / 0.0)"), "inf\n-inf\nNaN");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn promotes_integers_next_to_floats() {
+    assert_eq!(run("promotion", "x := 0.5\nx += 1\nprint(x, 7 / 2.0, 7 / 2, 1 < 1.5)"), "1.5\n3.5\n3\n1");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn prints_strings() {
+    assert_eq!(run("strings", "print(\"Small\", 1, \"नमस्ते\")\ns := \"tab\\there\"\nprint(s)"), "Small\n1\nनमस्ते\ntab\there");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn short_circuits_logical_operators() {
+    let source = "proc side() {\n    print(99)\n    return 1\n}\nx := 0\nprint(x != 0 && 10 / x > 1, 1 || side(), 0 || side())";
+    assert_eq!(run("short-circuit", source), "0\n1\n99\n1");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn prints_unsigned_numbers() {
+    assert_eq!(
+        run("unsigned", "print(18446744073709551615u64, 9223372036854775808u64, 18446744073709551615u64 / 2)"),
+        "18446744073709551615\n9223372036854775808\n9223372036854775807"
+    );
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn prints_interpolated_strings() {
+    let source = "x := 41\nprint(\"x is {x + 1}, half is {x / 2.0}\", \"nested {\"in {x}\"} and {{braces}}\")";
+    assert_eq!(run("interpolation", source), "x is 42, half is 20.5\nnested in 41 and {braces}");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn nests_more_for_loops_than_counter_registers() {
+    let source = "total := 0\nfor a in 0..2 { for b in 0..2 { for c in 0..2 { for d in 0..2 { for e in 0..2 { for f in 0..=2 {\n    total += f\n} } } } } }\nprint(total)";
+    assert_eq!(run("nested-for", source), "96");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn evaluates_for_ranges_in_order() {
+    let source = "proc show(x) {\n    print(x)\n    return x\n}\nfor i in show(1)..show(2) step show(3) { }";
+    assert_eq!(run("range-order", source), "1\n2\n3");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn prints_characters() {
+    assert_eq!(run("chars", "c := 'a'\nprint(c, 'é', 'क', '😀', c < 'b')"), "a\né\nक\n😀\n1");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn reports_division_by_zero() {
+    let stderr = run_failing("divide-zero", "x := 0\nprint(1)\nprint(7 / x)\nprint(2)\n");
+    assert!(stderr.starts_with("error: division by zero at main.df:3:7\n"), "{}", stderr);
+    assert!(stderr.contains("exit status: 1"), "{}", stderr);
+
+    let stderr = run_failing("remainder-zero", "x := 0\nprint(7 % x)\n");
+    assert!(stderr.starts_with("error: division by zero at main.df:2:7\n"), "{}", stderr);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn divides_i64_min_by_minus_one() {
+    let source = "x := -1\nm := -9223372036854775808\nprint(m / x, m % x, 7 / x, 7 % x)\n";
+    assert_eq!(run("minus-one", source), "-9223372036854775808\n0\n-7\n0");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn passes_arguments_past_the_registers() {
+    let source = "proc weigh(a, b, c, d, e, f, g, h) {\n    return a + 2 * b + 3 * c + 4 * d + 5 * e + 6 * f + 7 * g + 8 * h\n}\nprint(weigh(1, 2, 3, 4, 5, 6, 7, 8))\n";
+    assert_eq!(run("stack-arguments", source), "204");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn calls_procs_recursively() {
+    let source = "proc fact(n) {\n    if n < 2 { return 1 }\n    return n * fact(n - 1)\n}\nproc fib(n) {\n    if n < 2 { return n }\n    return fib(n - 1) + fib(n - 2)\n}\nprint(fact(20), fib(20))\n";
+    assert_eq!(run("recursion", source), "2432902008176640000\n6765");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn keeps_for_counters_across_calls() {
+    /* count's loops use the same counter registers as the loops calling it */
+    let source = "proc count(n) {\n    total := 0\n    for a in 0..n { for b in 0..n { for c in 0..n { for d in 0..n {\n        total = total + 1\n    } } } }\n    return total\n}\nfor a in 0..2 { for b in 0..2 { for c in 0..3 { for d in 0..3 {\n    print(a * 1000 + b * 100 + c * 10 + d + count(2) - 16)\n} } } }\n";
+    let expected: Vec<String> = (0..2)
+        .flat_map(|a| (0..2).flat_map(move |b| (0..3).flat_map(move |c| (0..3).map(move |d| (a * 1000 + b * 100 + c * 10 + d).to_string()))))
+        .collect();
+    assert_eq!(run("counters-across-calls", source), expected.join("\n"));
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn runs_while_loops() {
+    assert_eq!(run("while", "i := 0\nwhile i < 3 {\n    print(i)\n    i = i + 1\n}\n"), "0\n1\n2");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn breaks_out_of_loops_with_values() {
+    let source = "i := 0\nx := loop {\n    i = i + 1\n    if i == 6 { break i * 10 }\n}\ny := outer: loop {\n    loop {\n        break outer 7\n    }\n}\nprint(x, y)\n";
+    assert_eq!(run("break-value", source), "60\n7");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn continues_labelled_loops() {
+    let source = "outer: for a in 0..3 {\n    for b in 0..3 {\n        if b == 1 { continue outer }\n        print(a * 10 + b)\n    }\n}\n";
+    assert_eq!(run("continue-label", source), "0\n10\n20");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn breaking_out_of_for_loops_drops_their_ranges() {
+    /* the 1000 is on the stack under both ranges when the break leaves them */
+    let source = "print(1000 + done: loop {\n    for k in 0..10 {\n        for m in 0..10 {\n            if k == 3 { break done m + 5 }\n        }\n    }\n})\n";
+    assert_eq!(run("break-for", source), "1005");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn takes_the_first_true_branch() {
+    let source = "for n in 0..4 {\n    if n == 0 {\n        print(10)\n    } else if n == 1 {\n        print(11)\n    }\n    else if n == 2 { print(12) }\n    else {\n        print(13)\n    }\n}\nif 0 { print(1) }\n";
+    assert_eq!(run("else-if", source), "10\n11\n12\n13");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn stops_for_loops_at_the_ends_of_i64() {
+    let source = "for i in 9223372036854775806..=9223372036854775807 { print(i) }\nfor i in -9223372036854775807..=-9223372036854775808 step -1 { print(i) }\n";
+    let expected = "9223372036854775806\n9223372036854775807\n-9223372036854775807\n-9223372036854775808";
+    assert_eq!(run("for-overflow", source), expected);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn reports_a_zero_step() {
+    let stderr = run_failing("zero-step", "s := 0\nfor i in 0..3 step s { print(i) }\n");
+    assert!(stderr.starts_with("error: for loop step is 0 at main.df:2:20\n"), "{}", stderr);
+    assert!(stderr.contains("exit status: 1"), "{}", stderr);
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn runs_programs_written_in_nepali() {
+    let source = "#!keywords nepali\nसंख्या := १२ + ३\nछाप(संख्या)\nयदि संख्या > १० { छाप(सत्य) } नत्र { छाप(असत्य) }\n";
+    assert_eq!(run("nepali-directive", source), "15\n1");
+
+    let source = "हरेक अंक भित्र ०..३ { छाप(अंक * १०) }\n";
+    assert_eq!(run_with_args("nepali-flag", source, &["--keywords=nepali"]), "0\n10\n20");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn binds_operators_by_precedence_from_the_left() {
+    let source = "print(1 + 2 == 3, 2 - 3 - 4, 100 / 10 / 5, 2 + 3 * 4, 17 % 5 * 2, 1 < 2 == 1, 1 || 0 && 0)\n";
+    assert_eq!(run("precedence", source), "1\n-5\n2\n14\n4\n1\n1");
+}
+
+#[test]
+#[ignore = "needs nasm and ld"]
+fn reads_number_literals() {
+    assert_eq!(run("literals", "print(0xFF, 0b1010, 0o755, 1_000, 1.5e-3)\n"), "255\n10\n493\n1000\n0.0015");
+}